@@ -0,0 +1,172 @@
+use super::{
+    EdgeSnap, InsertStrategy, NearbyIterator, Options, PersistentRTree, Rect, ScanIterator,
+    SearchIterator, Shared,
+};
+use std::ops::{Add, Mul, Sub};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
+
+/// A thread safe RTree for many readers and one writer at a time.
+///
+/// Readers call [`search`](ConcurrentRTree::search),
+/// [`nearby`](ConcurrentRTree::nearby) and [`scan`](ConcurrentRTree::scan),
+/// or take a [`snapshot`](ConcurrentRTree::snapshot) for anything else.
+/// Each read sees a consistent view of the tree as of the last completed
+/// write, and holds no locks while it iterates. Writers apply a batch of
+/// changes with [`write`](ConcurrentRTree::write) to a private copy of the
+/// tree, which is then published for new reads when the batch is done.
+///
+/// The copies are [`PersistentRTree`]s, which run the code of [`RTree`] and
+/// share their nodes, so that publishing a batch is O(1) and a batch only
+/// copies the paths that it changes. A plain [`RTree`] would have to be
+/// copied whole for every batch, or block readers while it changes. The
+/// sharing is why changes need data that is `Clone`.
+///
+/// The tree is `Send` and `Sync` when `C` and `T` are `Send` and `Sync` and
+/// `S` is `Send` and `Sync`. Both are needed even for `Send`, because the
+/// readers share the published tree.
+///
+/// [`RTree`]: super::RTree
+pub struct ConcurrentRTree<const D: usize, C, T, S = EdgeSnap>
+where
+    C: PartialOrd + Copy + Default,
+{
+    current: RwLock<Arc<PersistentRTree<D, C, T, S>>>,
+    writer: Mutex<PersistentRTree<D, C, T, S>>,
+}
+
+impl<const D: usize, C, T, S> Default for ConcurrentRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    T: Clone,
+    S: InsertStrategy + Default + Clone,
+{
+    fn default() -> ConcurrentRTree<D, C, T, S> {
+        ConcurrentRTree::from(PersistentRTree::default())
+    }
+}
+
+impl<const D: usize, C, T, S> From<PersistentRTree<D, C, T, S>> for ConcurrentRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    T: Clone,
+    S: InsertStrategy + Clone,
+{
+    fn from(tr: PersistentRTree<D, C, T, S>) -> ConcurrentRTree<D, C, T, S> {
+        ConcurrentRTree {
+            current: RwLock::new(Arc::new(tr.snapshot())),
+            writer: Mutex::new(tr),
+        }
+    }
+}
+
+impl<const D: usize, C, T: Clone> ConcurrentRTree<D, C, T>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    pub fn new() -> ConcurrentRTree<D, C, T> {
+        ConcurrentRTree::from(PersistentRTree::new())
+    }
+    /// Creates an empty tree with the provided node fanout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options are invalid, see [`RTree::with_options`](super::RTree::with_options).
+    pub fn with_options(opts: Options) -> ConcurrentRTree<D, C, T> {
+        ConcurrentRTree::from(PersistentRTree::with_options(opts))
+    }
+}
+
+impl<const D: usize, C, T, S> ConcurrentRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    T: Clone,
+    S: InsertStrategy + Clone,
+{
+    /// Returns the tree as of the last completed write. The snapshot does
+    /// not change, and it does not block writers.
+    pub fn snapshot(&self) -> Arc<PersistentRTree<D, C, T, S>> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+    /// Calls `f` with the items that intersect the rect, as of the last
+    /// completed write.
+    pub fn search<F, R>(&self, rect: Rect<D, C>, f: F) -> R
+    where
+        F: FnOnce(SearchIterator<'_, D, C, T, Shared>) -> R,
+    {
+        f(self.snapshot().search(rect))
+    }
+    /// Calls `f` with the items in order of distance, as of the last
+    /// completed write.
+    pub fn nearby<F, G, R>(&self, dist: F, f: G) -> R
+    where
+        F: for<'a> FnMut(Rect<D, C>, Option<&'a T>) -> C,
+        G: for<'a> FnOnce(NearbyIterator<'a, D, C, T, F, Shared>) -> R,
+    {
+        f(self.snapshot().nearby(dist))
+    }
+    /// Calls `f` with every item, as of the last completed write.
+    pub fn scan<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(ScanIterator<'_, D, C, T, Shared>) -> R,
+    {
+        f(self.snapshot().scan())
+    }
+    pub fn len(&self) -> usize {
+        self.snapshot().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.snapshot().is_empty()
+    }
+    /// Applies a batch of changes, and then publishes the result for new
+    /// snapshots. Other writers wait until the batch is done. Readers are
+    /// never blocked, and they do not see any of the changes until the
+    /// batch is done.
+    ///
+    /// When the batch panics none of its changes are published, and the
+    /// next write starts over from the last published tree.
+    pub fn write<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut PersistentRTree<D, C, T, S>) -> R,
+    {
+        let mut writer = self.lock_writer();
+        let res = f(&mut writer);
+        let snap = Arc::new(writer.snapshot());
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = snap;
+        res
+    }
+    pub fn insert(&self, rect: Rect<D, C>, data: T) {
+        self.write(|tr| tr.insert(rect, data))
+    }
+    pub fn remove_by<F>(&self, rect: Rect<D, C>, pred: F) -> Option<(Rect<D, C>, T)>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.write(|tr| tr.remove_by(rect, pred))
+    }
+    fn lock_writer(&self) -> MutexGuard<'_, PersistentRTree<D, C, T, S>> {
+        match self.writer.lock() {
+            Ok(writer) => writer,
+            Err(poisoned) => {
+                // a batch panicked, so throw away whatever it did
+                let mut writer = poisoned.into_inner();
+                *writer = (*self.snapshot()).clone();
+                self.writer.clear_poison();
+                writer
+            }
+        }
+    }
+}
+
+impl<const D: usize, C, T, S> ConcurrentRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    T: Clone + PartialEq,
+    S: InsertStrategy + Clone,
+{
+    pub fn remove(&self, rect: Rect<D, C>, data: &T) -> Option<(Rect<D, C>, T)> {
+        self.write(|tr| tr.remove(rect, data))
+    }
+}
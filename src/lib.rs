@@ -1,24 +1,182 @@
+#![allow(clippy::needless_range_loop)]
+
+mod concurrent;
+pub mod mapped;
+pub mod paged;
+pub mod persistent;
+mod polygon;
+mod predicate;
+#[cfg(feature = "serde")]
+mod serialize;
+mod strategy;
+#[cfg(any(feature = "svg", test))]
+pub mod svg;
 #[cfg(test)]
 mod test;
+pub mod wal;
+
+pub use concurrent::ConcurrentRTree;
+pub use mapped::{Codec, MappedRTree};
+pub use paged::{FilePageStore, MemoryPageStore, PageStore, PagedRTree};
+pub use persistent::PersistentRTree;
+pub use polygon::{PolygonItem, SearchPolygonIterator};
+pub use predicate::{Contains, Intersects, Segment, SpatialPredicate, Within};
+pub use strategy::{Bounded, EdgeSnap, InsertStrategy, RStar, SplitStrategy};
+#[cfg(feature = "svg")]
+pub use svg::{Svg, ToF64};
+pub use wal::LoggedRTree;
 
 extern crate pqueue;
 
 use pqueue::Queue;
 use std::cmp::Ordering;
 use std::default::Default;
+use std::fmt;
 use std::ops::Add;
+use std::ops::Deref;
+use std::ops::Div;
 use std::ops::Mul;
 use std::ops::Sub;
+use std::sync::Arc;
 
 const MAX_ITEMS: usize = 32;
 const MIN_ITEMS: usize = MAX_ITEMS * 20 / 100;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// Options for the node fanout of an RTree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Options {
+    /// A node is split once it reaches this number of entries. Defaults
+    /// to 32.
+    pub max_items: usize,
+    /// The fewest number of entries allowed in a node, other than the root.
+    /// Nodes that fall below this are removed and their items reinserted.
+    /// Defaults to 20% of the default max_items.
+    pub min_items: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            max_items: MAX_ITEMS,
+            min_items: MIN_ITEMS,
+        }
+    }
+}
+
+impl Options {
+    // Both halves of a split must be able to hold min_items, and a packed
+    // node must have room for at least two entries.
+    fn check(&self) -> Result<(), String> {
+        if self.max_items < 3 {
+            return Err(format!(
+                "max_items must be at least 3, got {}",
+                self.max_items
+            ));
+        }
+        if self.min_items < 1 || self.min_items * 2 > self.max_items {
+            return Err(format!(
+                "min_items must be between 1 and half of max_items ({}), got {}",
+                self.max_items, self.min_items
+            ));
+        }
+        Ok(())
+    }
+    fn validate(&self) {
+        if let Err(msg) = self.check() {
+            panic!("{}", msg);
+        }
+    }
+}
+
+/// A broken invariant of a tree, which is found by [`RTree::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationError {
+    /// The child indexes that lead from the root to the node or item that is
+    /// broken. Empty for the root and for the length of the tree.
+    pub path: Vec<usize>,
+    pub kind: Violation,
+}
+
+/// The invariant that a [`ValidationError`] is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// The rect of a node is not the union of its children.
+    Rect,
+    /// A node has this number of children, which is outside of the fanout.
+    Fanout(usize),
+    /// An item above the leaf level, or a node at it.
+    Height,
+    /// The length of the tree is not this number of items that it holds.
+    Length(usize),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            Violation::Rect => write!(f, "node rect is not the union of its children")?,
+            Violation::Fanout(len) => write!(f, "node has {} children", len)?,
+            Violation::Height => write!(f, "leaf is not at the height of the tree")?,
+            Violation::Length(count) => write!(f, "tree holds {} items", count)?,
+        }
+        write!(f, " at {:?}", self.path)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Quality metrics of a tree, which are returned by [`RTree::stats`]. The
+/// areas and margins are in the units of the coordinates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats<C> {
+    /// The metrics of every level, from the root down to the leaves.
+    pub levels: Vec<LevelStats<C>>,
+    /// The number of nodes.
+    pub nodes: usize,
+    /// The average number of children of a node, relative to the most that
+    /// fit before it's split.
+    pub fill_factor: f64,
+    /// The sum of the overlap areas of every pair of sibling nodes.
+    pub overlap: C,
+    /// The sum of the areas of the nodes that are not covered by their
+    /// children. This is approximate: the overlap of every pair of children
+    /// is taken out of their covered area, which is exact unless three or
+    /// more children share space, and then the dead space comes out too
+    /// large.
+    pub dead_space: C,
+    /// The sum of the margins of the nodes.
+    pub margin: C,
+    /// The approximate number of bytes that the nodes take on the heap, not
+    /// including anything that the items own.
+    pub memory: usize,
+}
+
+/// The metrics of the nodes at a single level of a tree, see [`Stats`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LevelStats<C> {
+    pub nodes: usize,
+    pub fill_factor: f64,
+    pub overlap: C,
+    pub dead_space: C,
+    pub margin: C,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "C: serde::Serialize",
+        deserialize = "C: serde::Deserialize<'de> + Copy"
+    ))
+)]
 pub struct Rect<const D: usize, C>
 where
     C: Default,
 {
+    #[cfg_attr(feature = "serde", serde(with = "serialize::coords"))]
     pub min: [C; D],
+    #[cfg_attr(feature = "serde", serde(with = "serialize::coords"))]
     pub max: [C; D],
 }
 
@@ -38,6 +196,80 @@ fn max<C: PartialOrd>(a: C, b: C) -> C {
     }
 }
 
+// Evenly spreads len entries over count tiles.
+struct Tiles {
+    len: usize,
+    count: usize,
+}
+
+impl Tiles {
+    // Returns the index of the first entry in the tile.
+    fn start(&self, tile: usize) -> usize {
+        self.len * tile / self.count
+    }
+}
+
+// Returns the smallest number of slices per axis that can hold count tiles
+// over the provided number of dimensions.
+fn slices(count: usize, dims: usize) -> usize {
+    let mut slices = (count as f64).powf(1.0 / dims as f64).ceil() as usize;
+    while slices > 1 && (slices - 1).checked_pow(dims as u32).unwrap_or(usize::MAX) >= count {
+        slices -= 1;
+    }
+    while slices.checked_pow(dims as u32).unwrap_or(usize::MAX) < count {
+        slices += 1;
+    }
+    slices.max(1)
+}
+
+// Returns the index of a cell along a Hilbert curve that fills a grid of
+// 2^bits cells per axis. This is the transpose algorithm by John Skilling,
+// "Programming the Hilbert curve", which works for any number of dimensions.
+fn hilbert<const D: usize>(mut x: [u32; D], bits: u32) -> u64 {
+    if D == 0 || bits == 0 {
+        return 0;
+    }
+    let m = 1u32 << (bits - 1);
+    // inverse undo excess work
+    let mut q = m;
+    while q > 1 {
+        let p = q - 1;
+        for i in 0..D {
+            if x[i] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+        q >>= 1;
+    }
+    // gray encode
+    for i in 1..D {
+        x[i] ^= x[i - 1];
+    }
+    let mut t = 0;
+    let mut q = m;
+    while q > 1 {
+        if x[D - 1] & q != 0 {
+            t ^= q - 1;
+        }
+        q >>= 1;
+    }
+    for i in 0..D {
+        x[i] ^= t;
+    }
+    // interleave the transposed bits into a single index
+    let mut h = 0u64;
+    for b in (0..bits).rev() {
+        for i in 0..D {
+            h = (h << 1) | ((x[i] >> b) & 1) as u64;
+        }
+    }
+    h
+}
+
 fn compare<C: PartialOrd>(a: C, b: C) -> Ordering {
     if a < b {
         Ordering::Less
@@ -58,7 +290,7 @@ where
     pub fn new_point(point: [C; D]) -> Rect<D, C> {
         Rect::new(point, point)
     }
-    fn expand(&mut self, rect: &Rect<D, C>) {
+    pub fn expand(&mut self, rect: &Rect<D, C>) {
         for i in 0..D {
             if rect.min[i] < self.min[i] {
                 self.min[i] = rect.min[i];
@@ -83,7 +315,9 @@ where
         }
         axis
     }
-    fn contains(&self, rect: &Rect<D, C>) -> bool {
+    /// Returns true when the rect is completely inside of this one, edges
+    /// included. Never true for zero dimensions.
+    pub fn contains(&self, rect: &Rect<D, C>) -> bool {
         if D == 0 {
             return false;
         }
@@ -94,7 +328,9 @@ where
         }
         true
     }
-    fn intersects(&self, rect: &Rect<D, C>) -> bool {
+    /// Returns true when the rects share any point, edges included. Never
+    /// true for zero dimensions.
+    pub fn intersects(&self, rect: &Rect<D, C>) -> bool {
         if D == 0 {
             return false;
         }
@@ -105,6 +341,7 @@ where
         }
         true
     }
+    #[allow(clippy::neg_cmp_op_on_partial_ord)]
     fn on_edge(&self, rect: &Rect<D, C>) -> bool {
         for i in 0..D {
             if !(rect.min[i] > self.min[i]) || !(rect.max[i] < self.max[i]) {
@@ -113,7 +350,7 @@ where
         }
         false
     }
-    fn area(&self) -> C {
+    pub fn area(&self) -> C {
         if D == 0 {
             return Default::default();
         }
@@ -123,7 +360,36 @@ where
         }
         area
     }
-    fn unioned_area(&self, rect: &Rect<D, C>) -> C {
+    pub fn margin(&self) -> C {
+        let mut margin = Default::default();
+        for i in 0..D {
+            margin = margin + (self.max[i] - self.min[i]);
+        }
+        margin
+    }
+    pub fn overlap_area(&self, rect: &Rect<D, C>) -> C {
+        if !self.intersects(rect) {
+            return Default::default();
+        }
+        let mut area = min(self.max[0], rect.max[0]) - max(self.min[0], rect.min[0]);
+        for i in 1..D {
+            area = area * (min(self.max[i], rect.max[i]) - max(self.min[i], rect.min[i]));
+        }
+        area
+    }
+    // Returns the squared distance between the centers of the rects, where
+    // both centers are doubled to avoid a division.
+    fn center_dist(&self, rect: &Rect<D, C>) -> C {
+        let mut dist = Default::default();
+        for i in 0..D {
+            let a = self.min[i] + self.max[i];
+            let b = rect.min[i] + rect.max[i];
+            let x = if a > b { a - b } else { b - a };
+            dist = dist + x * x;
+        }
+        dist
+    }
+    pub fn unioned_area(&self, rect: &Rect<D, C>) -> C {
         if D == 0 {
             return Default::default();
         }
@@ -133,6 +399,35 @@ where
         }
         area
     }
+    // Returns the Hilbert index of the center of the rect, where the center
+    // is snapped to a grid that spans the bounds. Only addition and
+    // subtraction are used, so this works for integer coordinates too, and
+    // no value grows past the size of the bounds, so nothing overflows
+    // that the size itself does not.
+    fn hilbert(&self, bounds: &Rect<D, C>) -> u64 {
+        if D == 0 {
+            return 0;
+        }
+        let bits = (64 / D).clamp(1, 16) as u32;
+        let mut cell = [0u32; D];
+        for i in 0..D {
+            // bisects the bounds, where the center is in the upper half
+            // when a + b >= size, which avoids dividing it by two. The
+            // remainder of each step is kept within the size.
+            let size = bounds.max[i] - bounds.min[i];
+            let a = self.min[i] - bounds.min[i];
+            let b = self.max[i] - bounds.min[i];
+            let mut upper = a >= size - b;
+            let mut pos = if upper { a - (size - b) } else { a + b };
+            cell[i] = upper as u32;
+            for _ in 1..bits {
+                upper = pos >= size - pos;
+                pos = if upper { pos - (size - pos) } else { pos + pos };
+                cell[i] = cell[i] << 1 | upper as u32;
+            }
+        }
+        hilbert(cell, bits)
+    }
     pub fn box_dist(&self, rect: &Rect<D, C>) -> C {
         let zero = Default::default();
         if D == 0 {
@@ -149,6 +444,48 @@ where
     }
 }
 
+impl<const D: usize, C: From<f32>> Rect<D, C>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Div<Output = C> + Default,
+{
+    // Returns where the ray enters the rect, as the multiple of the
+    // direction from the origin, or none when the ray misses the rect
+    // before max_t. The slab of each axis cuts the ray down to an
+    // interval, and the ray hits the rect when the intervals overlap.
+    fn ray_entry(&self, origin: &[C; D], dir: &[C; D], max_t: C) -> Option<C> {
+        if D == 0 {
+            return None;
+        }
+        let zero = Default::default();
+        let (mut near, mut far) = (zero, max_t);
+        for i in 0..D {
+            if dir[i] == zero {
+                // parallel to the slab, so the ray is either always or
+                // never inside of it
+                if origin[i] < self.min[i] || origin[i] > self.max[i] {
+                    return None;
+                }
+                continue;
+            }
+            let mut t0 = (self.min[i] - origin[i]) / dir[i];
+            let mut t1 = (self.max[i] - origin[i]) / dir[i];
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > near {
+                near = t0;
+            }
+            if t1 < far {
+                far = t1;
+            }
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+}
+
 impl<const D: usize, C: Copy + Default> Default for Rect<D, C> {
     fn default() -> Rect<D, C> {
         Rect{
@@ -158,30 +495,142 @@ impl<const D: usize, C: Copy + Default> Default for Rect<D, C> {
     }
 }
 
-enum Data<const D: usize, C, T>
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "C: serde::Serialize, T: serde::Serialize",
+        deserialize = "C: serde::Deserialize<'de>, T: serde::Deserialize<'de>"
+    ))
+)]
+enum Data<const D: usize, C, T, K: Storage = Owned>
 where
     C: PartialOrd + Copy + Default,
 {
     Item(T),
-    Nodes(Box<Vec<Node<D, C, T>>>),
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            serialize_with = "serialize::nodes::serialize::<K, _, _>",
+            deserialize_with = "serialize::nodes::deserialize::<K, _, _>"
+        )
+    )]
+    Nodes(K::Nodes<Node<D, C, T, K>>),
 }
 
-struct Node<const D: usize, C, T>
+// The storage decides how the children of a node are held, see Storage.
+// With the default, Owned, a node is Send when C and T are Send, and Sync
+// when they are Sync.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(
+        serialize = "C: serde::Serialize, T: serde::Serialize",
+        deserialize = "C: serde::Deserialize<'de>, T: serde::Deserialize<'de>"
+    ))
+)]
+struct Node<const D: usize, C, T, K: Storage = Owned>
 where
     C: PartialOrd + Copy + Default,
 {
     rect: Rect<D, C>,
-    data: Data<D, C, T>,
+    data: Data<D, C, T, K>,
+}
+
+// Cloning copies the nodes that the storage owns, so an RTree clone is deep
+// and a PersistentRTree clone only shares the children of the root.
+impl<const D: usize, C, T: Clone, K: Storage> Clone for Node<D, C, T, K>
+where
+    C: PartialOrd + Copy + Default,
+{
+    fn clone(&self) -> Node<D, C, T, K> {
+        Node {
+            rect: self.rect,
+            data: match &self.data {
+                Data::Item(data) => Data::Item(data.clone()),
+                Data::Nodes(nodes) => Data::Nodes(K::clone_nodes(nodes)),
+            },
+        }
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// How the children of the branch nodes of a tree are held. An [`RTree`]
+/// uses [`Owned`], and a [`PersistentRTree`] uses [`Shared`]. The trait is
+/// sealed.
+pub trait Storage: sealed::Sealed {
+    #[doc(hidden)]
+    type Nodes<N>: Deref<Target = Vec<N>>;
+    #[doc(hidden)]
+    fn new_nodes<N>(nodes: Vec<N>) -> Self::Nodes<N>;
+    #[doc(hidden)]
+    fn clone_nodes<N: Clone>(nodes: &Self::Nodes<N>) -> Self::Nodes<N>;
+}
+
+/// The children are owned by their parent, which is the default.
+#[derive(Clone, Copy, Debug)]
+pub struct Owned;
+
+/// The children are behind an `Arc`, so that copies of a tree can share
+/// them. Both `C` and `T` must be `Send` and `Sync` for the tree, or any of
+/// its iterators, to be either.
+#[derive(Clone, Copy, Debug)]
+pub struct Shared;
+
+impl sealed::Sealed for Owned {}
+impl sealed::Sealed for Shared {}
+
+impl Storage for Owned {
+    #[allow(clippy::box_collection)]
+    type Nodes<N> = Box<Vec<N>>;
+    fn new_nodes<N>(nodes: Vec<N>) -> Box<Vec<N>> {
+        Box::new(nodes)
+    }
+    fn clone_nodes<N: Clone>(nodes: &Box<Vec<N>>) -> Box<Vec<N>> {
+        nodes.clone()
+    }
+}
+
+impl Storage for Shared {
+    type Nodes<N> = Arc<Vec<N>>;
+    fn new_nodes<N>(nodes: Vec<N>) -> Arc<Vec<N>> {
+        Arc::new(nodes)
+    }
+    fn clone_nodes<N: Clone>(nodes: &Arc<Vec<N>>) -> Arc<Vec<N>> {
+        Arc::clone(nodes)
+    }
 }
 
-impl<const D: usize, C, T: PartialEq> Node<D, C, T>
+// How a tree gets write access to the children of a node. Owned children
+// are written in place, and shared children are first copied when another
+// copy of the tree still holds them.
+trait Writer<N>: Storage {
+    fn nodes_mut(nodes: &mut Self::Nodes<N>) -> &mut Vec<N>;
+}
+
+impl<N> Writer<N> for Owned {
+    fn nodes_mut(nodes: &mut Box<Vec<N>>) -> &mut Vec<N> {
+        nodes
+    }
+}
+
+impl<N: Clone> Writer<N> for Shared {
+    fn nodes_mut(nodes: &mut Arc<Vec<N>>) -> &mut Vec<N> {
+        Arc::make_mut(nodes)
+    }
+}
+
+impl<const D: usize, C, T, K: Storage> Node<D, C, T, K>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
 {
-    fn new(rect: Rect<D, C>) -> Node<D, C, T> {
+    fn new(rect: Rect<D, C>, opts: &Options) -> Node<D, C, T, K> {
         Node {
-            rect: rect,
-            data: Data::Nodes(Box::new(Vec::with_capacity(MAX_ITEMS))),
+            rect,
+            data: Data::Nodes(K::new_nodes(Vec::with_capacity(opts.max_items))),
         }
     }
     fn len(&self) -> usize {
@@ -190,130 +639,298 @@ where
             _ => panic!("not a branch node"),
         }
     }
-    fn nodes(&self) -> &Vec<Node<D, C, T>> {
+    fn nodes(&self) -> &Vec<Node<D, C, T, K>> {
         match &self.data {
             Data::Nodes(nodes) => nodes,
             _ => panic!("not a branch node"),
         }
     }
-    fn nodes_mut(&mut self) -> &mut Vec<Node<D, C, T>> {
-        match &mut self.data {
-            Data::Nodes(nodes) => nodes,
-            _ => panic!("not a branch node"),
-        }
-    }
     fn item(&self) -> &T {
         match &self.data {
             Data::Item(item) => item,
             _ => panic!("not a leaf node"),
         }
     }
-    fn choose_least_enlargement(&self, rect: &Rect<D, C>) -> usize {
-        if D == 0 {
-            return 0;
+    #[allow(clippy::len_zero)]
+    fn recalc(&mut self) {
+        let nodes = self.nodes();
+        if nodes.len() == 0 {
+            return;
+        }
+        let mut rect = nodes[0].rect;
+        for i in 1..nodes.len() {
+            rect.expand(&nodes[i].rect);
+        }
+        self.rect = rect
+    }
+    // Adds the metrics of the node and its subtree to the levels, where
+    // the fill factors are sums until the averages are taken.
+    fn stats(&self, depth: usize, opts: &Options, levels: &mut Vec<LevelStats<C>>) -> usize {
+        let nodes = self.nodes();
+        if levels.len() == depth {
+            levels.push(LevelStats::default());
+        }
+        let level = &mut levels[depth];
+        level.nodes += 1;
+        level.fill_factor += nodes.len() as f64 / (opts.max_items - 1) as f64;
+        level.margin = level.margin + self.rect.margin();
+        // the union of the children, up to the space that three or more of
+        // them share
+        let mut covered = C::default();
+        let mut overlap = C::default();
+        for i in 0..nodes.len() {
+            covered = covered + nodes[i].rect.area();
+            for j in i + 1..nodes.len() {
+                overlap = overlap + nodes[i].rect.overlap_area(&nodes[j].rect);
+            }
+        }
+        let area = self.rect.area();
+        if covered < area + overlap {
+            level.dead_space = level.dead_space + (area + overlap - covered);
         }
-        let mut j = 0;
-        let mut jenlargement = rect.min[0];
-        let mut jarea = rect.min[0];
+        let mut memory = std::mem::size_of::<Vec<Node<D, C, T, K>>>()
+            + nodes.capacity() * std::mem::size_of::<Node<D, C, T, K>>();
+        if let Some(Data::Nodes(_)) = nodes.first().map(|node| &node.data) {
+            for node in nodes.iter() {
+                memory += node.stats(depth + 1, opts, levels);
+            }
+            let level = &mut levels[depth + 1];
+            level.overlap = level.overlap + overlap;
+        }
+        memory
+    }
+    // Checks the node and its subtree, and returns the number of items in
+    // it. The path leads to the node, and it is empty for the root.
+    fn validate(
+        &self,
+        height: usize,
+        opts: &Options,
+        path: &mut Vec<usize>,
+    ) -> Result<usize, ValidationError> {
+        let nodes = self.nodes();
+        let min_items = if path.is_empty() { 1 } else { opts.min_items };
+        let kind = if nodes.len() < min_items || nodes.len() >= opts.max_items {
+            Some(Violation::Fanout(nodes.len()))
+        } else {
+            let mut rect = nodes[0].rect;
+            for node in nodes.iter().skip(1) {
+                rect.expand(&node.rect);
+            }
+            (rect != self.rect).then_some(Violation::Rect)
+        };
+        if let Some(kind) = kind {
+            return Err(ValidationError {
+                path: path.clone(),
+                kind,
+            });
+        }
+        let mut count = 0;
+        for i in 0..nodes.len() {
+            path.push(i);
+            count += match &nodes[i].data {
+                Data::Item(_) if height == 0 => 1,
+                Data::Nodes(_) if height > 0 => nodes[i].validate(height - 1, opts, path)?,
+                _ => {
+                    return Err(ValidationError {
+                        path: path.clone(),
+                        kind: Violation::Height,
+                    })
+                }
+            };
+            path.pop();
+        }
+        Ok(count)
+    }
+    // Finds the first item within the rect that the predicate returns true
+    // for, without changing anything. The path to the item is pushed from
+    // the leaf up.
+    fn find<F>(&self, rect: &Rect<D, C>, pred: &mut F, height: usize, path: &mut Vec<usize>) -> bool
+    where
+        F: FnMut(&T) -> bool,
+    {
         let nodes = self.nodes();
         for i in 0..nodes.len() {
-            let uarea = nodes[i].rect.unioned_area(rect);
-            let area = nodes[i].rect.area();
-            let enlargement = uarea - area;
-            if i == 0 || enlargement < jenlargement || (enlargement == jenlargement && area < jarea)
+            if height == 0 {
+                if !pred(nodes[i].item()) {
+                    continue;
+                }
+            } else if !nodes[i].rect.intersects(rect)
+                || !nodes[i].find(rect, pred, height - 1, path)
             {
-                j = i;
-                jenlargement = enlargement;
-                jarea = area;
+                continue;
             }
+            path.push(i);
+            return true;
         }
-        j
+        false
     }
-    fn choose_subtree(&self, rect: &Rect<D, C>) -> usize {
-        if D == 0 {
-            return 0;
+    #[allow(clippy::needless_borrow)]
+    pub fn search_flat<'a>(&'a self, rect: &Rect<D, C>, items: &mut Vec<(Rect<D, C>, &'a T)>) {
+        let nodes = self.nodes();
+        for i in 0..nodes.len() {
+            if nodes[i].rect.intersects(&rect) {
+                match &nodes[i].data {
+                    Data::Item(data) => items.push((nodes[i].rect, data)),
+                    _ => nodes[i].search_flat(&rect, items),
+                }
+            }
         }
-        self.choose_least_enlargement(&rect)
     }
-    fn insert(&mut self, rect: Rect<D, C>, data: T, height: usize) {
-        if height == 0 {
-            // leaf node
-            self.nodes_mut().push(Node {
-                rect: rect,
-                data: Data::Item(data),
-            });
+}
+
+impl<const D: usize, C, T, K: Writer<Node<D, C, T, K>>> Node<D, C, T, K>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    fn nodes_mut(&mut self) -> &mut Vec<Node<D, C, T, K>> {
+        match &mut self.data {
+            Data::Nodes(nodes) => K::nodes_mut(nodes),
+            _ => panic!("not a branch node"),
+        }
+    }
+    // Inserts the entry into the descendant node that is at the provided
+    // level, where the leaves are at level zero. Returns true when the rect
+    // of this node was recalculated because of a forced reinsert.
+    fn insert<S: InsertStrategy>(
+        &mut self,
+        entry: Node<D, C, T, K>,
+        level: usize,
+        height: usize,
+        opts: &Options,
+        strategy: &S,
+        forced: &mut ForcedReinsert<Node<D, C, T, K>>,
+    ) -> bool {
+        let rect = entry.rect;
+        if height == level {
+            self.nodes_mut().push(entry);
         } else {
             // branch node
-            let index = self.choose_subtree(&rect);
+            let index = if D == 0 {
+                0
+            } else {
+                strategy.choose_subtree(self.nodes(), &rect, height == 1)
+            };
             let nodes = self.nodes_mut();
             let child = &mut nodes[index];
-            child.insert(rect, data, height - 1);
-            if child.len() == MAX_ITEMS {
-                let right = child.split_largest_axis_edge_snap();
-                nodes.push(right);
+            let mut recalced = child.insert(entry, level, height - 1, opts, strategy, forced);
+            if child.len() == opts.max_items {
+                if forced.first(height - 1) {
+                    let crect = child.rect;
+                    let children = child.nodes_mut();
+                    strategy.reinsert(&crect, children, &mut forced.taken, opts);
+                    debug_assert!(
+                        children.len() >= opts.min_items,
+                        "the strategy reinserted too many children"
+                    );
+                }
+                if forced.taken.is_empty() {
+                    let right = child.split(opts, strategy);
+                    nodes.push(right);
+                } else {
+                    child.recalc();
+                    while let Some(entry) = forced.taken.pop() {
+                        forced.entries.push((entry, height - 1));
+                    }
+                    recalced = true;
+                }
+            }
+            if recalced {
+                self.recalc();
+                return true;
             }
         }
         if !self.rect.contains(&rect) {
             self.rect.expand(&rect);
         }
+        false
     }
-    fn recalc(&mut self) {
-        let nodes = self.nodes_mut();
-        if nodes.len() == 0 {
-            return;
-        }
-        let mut rect = nodes[0].rect;
-        for i in 1..nodes.len() {
-            rect.expand(&nodes[i].rect);
-        }
-        self.rect = rect
-    }
-    fn split_largest_axis_edge_snap(&mut self) -> Node<D, C, T> {
+    fn split<S: SplitStrategy>(&mut self, opts: &Options, strategy: &S) -> Node<D, C, T, K> {
         let rect = self.rect;
-        let axis = rect.largest_axis();
-        let mut right = Node::new(rect);
-        let lchilds = self.nodes_mut();
-        let rchilds = right.nodes_mut();
-        let mut i = 0;
-        while i < lchilds.len() {
-            let min = lchilds[i].rect.min[axis] - rect.min[axis];
-            let max = rect.max[axis] - lchilds[i].rect.max[axis];
-            if min < max {
-                // stay left
-                i += 1;
-            } else {
-                // move right
-                rchilds.push(lchilds.swap_remove(i));
-            }
-        }
-        // Make sure that both left and right nodes have at least
-        // MIN_ITEMS by moving items into underflowed nodes.
-        if lchilds.len() < MIN_ITEMS {
-            // reverse sort by min axis
-            rchilds.sort_by(|a, b| compare(b.rect.min[axis], a.rect.min[axis]));
-            while lchilds.len() < MIN_ITEMS {
-                lchilds.push(rchilds.pop().unwrap());
-            }
-        } else if rchilds.len() < MIN_ITEMS {
-            // reverse sort by max axis
-            lchilds.sort_by(|a, b| compare(b.rect.max[axis], a.rect.max[axis]));
-            while rchilds.len() < MIN_ITEMS {
-                rchilds.push(lchilds.pop().unwrap());
-            }
-        }
-        // recalculate and sort the nodes
+        let mut right = Node::new(rect, opts);
+        strategy.split(&rect, self.nodes_mut(), right.nodes_mut(), opts);
+        debug_assert!(
+            self.len() >= opts.min_items && right.len() >= opts.min_items,
+            "the strategy split off a side with too few children"
+        );
         self.recalc();
         right.recalc();
-        self.sort_by_axis(0);
-        right.sort_by_axis(0);
         right
     }
-    fn push(&mut self, child: Node<D, C, T>) {
+    fn push(&mut self, child: Node<D, C, T, K>) {
         self.nodes_mut().push(child);
     }
-    fn sort_by_axis(&mut self, axis: usize) {
-        let nodes = self.nodes_mut();
-        nodes.sort_by(|a, b| compare(a.rect.min[axis], b.rect.min[axis]));
+    fn from_children(mut children: Vec<Node<D, C, T, K>>, opts: &Options) -> Node<D, C, T, K> {
+        let mut node = Node::new(Rect::default(), opts);
+        node.nodes_mut().append(&mut children);
+        node.recalc();
+        node
+    }
+    // Sort-Tile-Recursive packing. The children are sliced into tiles along
+    // each axis in turn and every tile becomes a new parent node. The
+    // children are spread evenly over the tiles, which keeps every node
+    // between min_items and max_items-1.
+    fn pack_str(children: Vec<Node<D, C, T, K>>, opts: &Options) -> Vec<Node<D, C, T, K>> {
+        let len = children.len();
+        let count = (len + opts.max_items - 2) / (opts.max_items - 1);
+        let mut packed = Vec::with_capacity(count);
+        let tiles = Tiles { len, count };
+        Node::pack_axis(children, &tiles, 0, count, 0, &mut packed, opts);
+        packed
+    }
+    // Packs the children into parent nodes in the order that they are
+    // provided, such as Hilbert order.
+    fn pack_in_order(mut children: Vec<Node<D, C, T, K>>, opts: &Options) -> Vec<Node<D, C, T, K>> {
+        let len = children.len();
+        let count = (len + opts.max_items - 2) / (opts.max_items - 1);
+        let tiles = Tiles { len, count };
+        let mut packed = Vec::with_capacity(count);
+        for i in (0..count).rev() {
+            let slab = children.split_off(tiles.start(i));
+            packed.push(Node::from_children(slab, opts));
+        }
+        packed.reverse();
+        packed
+    }
+    // Packs the children that belong to the tiles from first to last into
+    // new parent nodes.
+    fn pack_axis(
+        mut children: Vec<Node<D, C, T, K>>,
+        tiles: &Tiles,
+        first: usize,
+        last: usize,
+        axis: usize,
+        packed: &mut Vec<Node<D, C, T, K>>,
+        opts: &Options,
+    ) {
+        let count = last - first;
+        if count == 1 {
+            packed.push(Node::from_children(children, opts));
+            return;
+        }
+        let slices = if axis + 1 < D {
+            slices(count, D - axis)
+        } else {
+            count
+        };
+        if axis < D {
+            children.sort_by(|a, b| {
+                compare(
+                    a.rect.min[axis] + a.rect.max[axis],
+                    b.rect.min[axis] + b.rect.max[axis],
+                )
+            });
+        }
+        let offset = tiles.start(first);
+        let mut slabs = Vec::with_capacity(slices);
+        for i in (0..slices).rev() {
+            let sfirst = first + count * i / slices;
+            let slast = first + count * (i + 1) / slices;
+            let slab = children.split_off(tiles.start(sfirst) - offset);
+            slabs.push((slab, sfirst, slast));
+        }
+        while let Some((slab, sfirst, slast)) = slabs.pop() {
+            Node::pack_axis(slab, tiles, sfirst, slast, axis + 1, packed, opts);
+        }
     }
     fn flatten_into(&mut self, reinsert: &mut Vec<(Rect<D, C>, T)>) {
         let nodes = self.nodes_mut();
@@ -324,180 +941,834 @@ where
             }
         }
     }
-    pub fn remove(
+    // Removes the item at the end of the path. Children that underflow on
+    // the way back up are flattened into reinsert. Returns the item, and
+    // whether the rect of this node was recalculated.
+    fn remove(
         &mut self,
-        rect: &Rect<D, C>,
-        data: &T,
+        path: &[usize],
         reinsert: &mut Vec<(Rect<D, C>, T)>,
+        opts: &Options,
+    ) -> ((Rect<D, C>, T), bool) {
+        let i = path[0];
+        let nodes = self.nodes_mut();
+        if path.len() == 1 {
+            let out = nodes.swap_remove(i);
+            let recalced = self.rect.on_edge(&out.rect);
+            if recalced {
+                self.recalc();
+            }
+            let data = match out.data {
+                Data::Item(data) => data,
+                _ => unreachable!(),
+            };
+            return ((out.rect, data), recalced);
+        }
+        let (removed, mut recalced) = nodes[i].remove(&path[1..], reinsert, opts);
+        if nodes[i].len() < opts.min_items {
+            let nrect = nodes[i].rect;
+            nodes.swap_remove(i).flatten_into(reinsert);
+            if !recalced {
+                recalced = self.rect.on_edge(&nrect);
+            }
+        }
+        if recalced {
+            self.recalc();
+        }
+        (removed, recalced)
+    }
+    // Moves the item to the new rect, but only when the new rect fits inside
+    // of the leaf that holds the item. The rects of the nodes on the path are
+    // recalculated as needed.
+    fn update(
+        &mut self,
+        old_rect: &Rect<D, C>,
+        data: &T,
+        new_rect: &Rect<D, C>,
         height: usize,
-    ) -> (Option<(Rect<D, C>, T)>, bool) {
+    ) -> Update
+    where
+        T: PartialEq,
+    {
         let nodes = self.nodes_mut();
         if height == 0 {
-            // remove from leaf
             for i in 0..nodes.len() {
-                if nodes[i].item() == data {
-                    let out = nodes.swap_remove(i);
-                    let recalced = self.rect.on_edge(&out.rect);
+                // equal data may be found more than once in a leaf
+                if nodes[i].rect == *old_rect && nodes[i].item() == data {
+                    if !self.rect.contains(new_rect) {
+                        return Update::NoFit;
+                    }
+                    let nodes = self.nodes_mut();
+                    let irect = nodes[i].rect;
+                    nodes[i].rect = *new_rect;
+                    let recalced = self.rect.on_edge(&irect);
                     if recalced {
                         self.recalc();
                     }
-                    return (
-                        Some((
-                            out.rect,
-                            match out.data {
-                                Data::Item(data) => data,
-                                _ => unreachable!(),
-                            },
-                        )),
-                        recalced,
-                    );
+                    return Update::Moved(recalced);
                 }
             }
         } else {
             for i in 0..nodes.len() {
-                if !nodes[i].rect.intersects(rect) {
-                    continue;
-                }
-                let (removed, mut recalced) = nodes[i].remove(rect, data, reinsert, height - 1);
-                if removed.is_none() {
+                if !nodes[i].rect.intersects(old_rect) {
                     continue;
                 }
-                let underflow = nodes[i].len() < MIN_ITEMS;
-                if underflow {
-                    let nrect = nodes[i].rect;
-                    nodes.swap_remove(i).flatten_into(reinsert);
-                    if !recalced {
-                        recalced = self.rect.on_edge(&nrect);
+                let crect = nodes[i].rect;
+                match nodes[i].update(old_rect, data, new_rect, height - 1) {
+                    Update::NotFound => continue,
+                    Update::Moved(true) if self.rect.on_edge(&crect) => {
+                        self.recalc();
+                        return Update::Moved(true);
                     }
+                    Update::Moved(_) => return Update::Moved(false),
+                    Update::NoFit => return Update::NoFit,
                 }
-                if recalced {
-                    self.recalc();
-                }
-                return (removed, recalced);
             }
         }
-        (None, false)
+        Update::NotFound
     }
-    pub fn search_flat<'a>(&'a self, rect: &Rect<D, C>, items: &mut Vec<(Rect<D, C>, &'a T)>) {
-        let nodes = self.nodes();
-        for i in 0..nodes.len() {
-            if nodes[i].rect.intersects(&rect) {
-                match &nodes[i].data {
-                    Data::Item(data) => items.push((nodes[i].rect, data)),
-                    _ => nodes[i].search_flat(&rect, items),
-                }
-            }
-        }
+    // Removes every item that intersects the rect, or every item when there
+    // is no rect, that the predicate returns true for. Children that
+    // underflow are flattened into reinsert. Returns true when the node was
+    // changed, in which case its rect has been recalculated.
+    fn drain<F>(
+        &mut self,
+        rect: Option<&Rect<D, C>>,
+        pred: &mut F,
+        drained: &mut Vec<(Rect<D, C>, T)>,
+        reinsert: &mut Vec<(Rect<D, C>, T)>,
+        height: usize,
+        opts: &Options,
+    ) -> bool
+    where
+        F: FnMut(&Rect<D, C>, &T) -> bool,
+    {
+        let nodes = self.nodes_mut();
+        let mut changed = false;
+        let mut i = 0;
+        if height == 0 {
+            while i < nodes.len() {
+                if rect.is_none_or(|rect| nodes[i].rect.intersects(rect))
+                    && pred(&nodes[i].rect, nodes[i].item())
+                {
+                    let out = nodes.swap_remove(i);
+                    match out.data {
+                        Data::Item(data) => drained.push((out.rect, data)),
+                        _ => unreachable!(),
+                    }
+                    changed = true;
+                } else {
+                    i += 1;
+                }
+            }
+        } else {
+            while i < nodes.len() {
+                if rect.is_none_or(|rect| nodes[i].rect.intersects(rect))
+                    && nodes[i].drain(rect, pred, drained, reinsert, height - 1, opts)
+                {
+                    changed = true;
+                    if nodes[i].len() < opts.min_items {
+                        nodes.swap_remove(i).flatten_into(reinsert);
+                        continue;
+                    }
+                }
+                i += 1;
+            }
+        }
+        if changed {
+            self.recalc();
+        }
+        changed
+    }
+}
+
+impl<const D: usize, C, T> Node<D, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    // Moves the node and all of its descendants into the storage.
+    fn into_storage<K: Storage>(self) -> Node<D, C, T, K> {
+        Node {
+            rect: self.rect,
+            data: match self.data {
+                Data::Item(data) => Data::Item(data),
+                Data::Nodes(nodes) => {
+                    let nodes = nodes.into_iter().map(Node::into_storage).collect();
+                    Data::Nodes(K::new_nodes(nodes))
+                }
+            },
+        }
     }
 }
 
-pub struct RTree<const D: usize, C, T: PartialEq>
+// The outcome of moving an item in place. Moved holds whether the rect of
+// the node changed.
+enum Update {
+    NotFound,
+    NoFit,
+    Moved(bool),
+}
+
+// Entries that were taken out of overflowed nodes by a strategy's forced
+// reinsert, along with the level they must be reinserted at. Each level
+// gets one chance at a forced reinsert per insert operation.
+struct ForcedReinsert<E> {
+    levels: u64,
+    taken: Vec<E>,
+    entries: Vec<(E, usize)>,
+}
+
+impl<E> ForcedReinsert<E> {
+    fn new() -> ForcedReinsert<E> {
+        ForcedReinsert {
+            levels: 0,
+            taken: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+    // Returns true, only once, for the first overflow on a level.
+    fn first(&mut self, level: usize) -> bool {
+        if level >= 64 || self.levels & (1 << level) != 0 {
+            return false;
+        }
+        self.levels |= 1 << level;
+        true
+    }
+}
+
+// Items print their rect and data, and branches print their rect and
+// children, which nests nicely with the alternate form.
+impl<const D: usize, C, T, K: Storage> fmt::Debug for Node<D, C, T, K>
+where
+    C: PartialOrd + Copy + Default + fmt::Debug,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.data {
+            Data::Item(data) => f
+                .debug_struct("Item")
+                .field("rect", &self.rect)
+                .field("data", data)
+                .finish(),
+            Data::Nodes(nodes) => f
+                .debug_struct("Node")
+                .field("rect", &self.rect)
+                .field("children", &**nodes)
+                .finish(),
+        }
+    }
+}
+
+impl<const D: usize, C, T, K: Storage> Bounded<D, C> for Node<D, C, T, K>
+where
+    C: PartialOrd + Copy + Default,
+{
+    fn rect(&self) -> &Rect<D, C> {
+        &self.rect
+    }
+}
+
+// Sorts the entries by the Hilbert index of their rect centers within the
+// bounds of all entries.
+fn sort_hilbert<const D: usize, C, E, F>(entries: &mut [E], rect: F)
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    F: Fn(&E) -> &Rect<D, C>,
+{
+    if entries.len() < 2 {
+        return;
+    }
+    let mut bounds = *rect(&entries[0]);
+    for entry in entries.iter().skip(1) {
+        bounds.expand(rect(entry));
+    }
+    entries.sort_by_cached_key(|entry| rect(entry).hilbert(&bounds));
+}
+
+/// The packing used for bulk loading a tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Packing {
+    /// Sort-Tile-Recursive, which slices the items along each axis in turn.
+    #[default]
+    Str,
+    /// Items are ordered by the Hilbert index of their centers within the
+    /// bounds of all items and then packed into nodes in that order.
+    Hilbert,
+}
+
+/// An R-tree of rects and their data.
+///
+/// The tree is `Send` when `C`, `T` and `S` are `Send`, and it is `Sync`
+/// when they are `Sync`, so any number of threads may search a shared tree.
+/// For searching while another thread changes the tree, see
+/// [`ConcurrentRTree`].
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(serialize = "C: serde::Serialize, T: serde::Serialize"))
+)]
+pub struct RTree<const D: usize, C, T, S = EdgeSnap, K: Storage = Owned>
 where
     C: PartialOrd + Copy + Default,
 {
-    root: Option<Node<D, C, T>>,
+    root: Option<Node<D, C, T, K>>,
+    #[cfg_attr(feature = "serde", serde(rename = "len"))]
     length: usize,
     height: usize,
+    #[cfg_attr(feature = "serde", serde(rename = "options"))]
+    opts: Options,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    strategy: S,
+}
+
+impl<const D: usize, C, T, S> RTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Default,
+{
+    // Moves every node into shared storage, for a PersistentRTree.
+    fn into_shared(self) -> RTree<D, C, T, S, Shared> {
+        RTree {
+            root: self.root.map(Node::into_storage),
+            length: self.length,
+            height: self.height,
+            opts: self.opts,
+            strategy: self.strategy,
+        }
+    }
+}
+
+impl<const D: usize, C, T, S: Clone> RTree<D, C, T, S, Shared>
+where
+    C: PartialOrd + Copy + Default,
+{
+    // Returns a copy of the tree that shares all of its nodes. Unlike a
+    // clone, the data does not need to be Clone, as the root is a branch.
+    fn share(&self) -> RTree<D, C, T, S, Shared> {
+        RTree {
+            root: self.root.as_ref().map(|root| Node {
+                rect: root.rect,
+                data: match &root.data {
+                    Data::Nodes(nodes) => Data::Nodes(Arc::clone(nodes)),
+                    Data::Item(_) => unreachable!(),
+                },
+            }),
+            length: self.length,
+            height: self.height,
+            opts: self.opts,
+            strategy: self.strategy.clone(),
+        }
+    }
+}
+
+impl<const D: usize, C, T, S: InsertStrategy + Default> Default for RTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    fn default() -> RTree<D, C, T, S> {
+        RTree::with_strategy(Options::default(), S::default())
+    }
 }
 
-impl<const D: usize, C, T: PartialEq> RTree<D, C, T>
+/// Shows the height, length and rect of the tree. The alternate form,
+/// `{:#?}`, also dumps the whole node hierarchy.
+impl<const D: usize, C, T, S, K: Storage> fmt::Debug for RTree<D, C, T, S, K>
+where
+    C: PartialOrd + Copy + Default + fmt::Debug,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alternate = f.alternate();
+        let mut s = f.debug_struct("RTree");
+        s.field("height", &self.height)
+            .field("len", &self.length)
+            .field("rect", &self.root.as_ref().map(|root| root.rect));
+        if alternate {
+            s.field("root", &self.root);
+        }
+        s.finish()
+    }
+}
+
+impl<const D: usize, C, T> RTree<D, C, T>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
 {
     pub fn new() -> RTree<D, C, T> {
+        RTree::with_options(Options::default())
+    }
+    /// Creates an empty tree with the provided node fanout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_items` is less than 3, or if `min_items` is zero or
+    /// more than half of `max_items`.
+    pub fn with_options(opts: Options) -> RTree<D, C, T> {
+        RTree::with_strategy(opts, EdgeSnap)
+    }
+    /// Builds a tree from a static set of items using Sort-Tile-Recursive
+    /// packing. This is much faster than inserting the items one at a time
+    /// and produces fully packed nodes.
+    pub fn bulk_load(items: Vec<(Rect<D, C>, T)>) -> RTree<D, C, T> {
+        RTree::bulk_load_with(items, Packing::Str, Options::default())
+    }
+    /// Builds a tree from a static set of items using the provided packing
+    /// and node fanout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options are invalid, see [`RTree::with_options`].
+    pub fn bulk_load_with(
+        items: Vec<(Rect<D, C>, T)>,
+        packing: Packing,
+        opts: Options,
+    ) -> RTree<D, C, T> {
+        RTree::bulk_load_with_strategy(items, packing, opts, EdgeSnap)
+    }
+}
+
+impl<const D: usize, C, T, S: InsertStrategy> RTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    /// Creates an empty tree with the provided node fanout, which uses the
+    /// strategy for choosing subtrees and splitting nodes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options are invalid, see [`RTree::with_options`].
+    pub fn with_strategy(opts: Options, strategy: S) -> RTree<D, C, T, S> {
+        opts.validate();
         RTree {
             root: None,
             length: 0,
             height: 0,
+            opts,
+            strategy,
+        }
+    }
+    /// Builds a tree from a static set of items, like
+    /// [`RTree::bulk_load_with`], that uses the strategy for later inserts.
+    pub fn bulk_load_with_strategy(
+        items: Vec<(Rect<D, C>, T)>,
+        packing: Packing,
+        opts: Options,
+        strategy: S,
+    ) -> RTree<D, C, T, S> {
+        let mut tr = RTree::with_strategy(opts, strategy);
+        tr.load(items, packing);
+        tr
+    }
+    // Packs the items into the tree, which must be empty.
+    fn load(&mut self, items: Vec<(Rect<D, C>, T)>, packing: Packing) {
+        let length = items.len();
+        let mut nodes: Vec<Node<D, C, T>> = items
+            .into_iter()
+            .map(|(rect, data)| Node {
+                rect,
+                data: Data::Item(data),
+            })
+            .collect();
+        if nodes.is_empty() {
+            return;
         }
+        if packing == Packing::Hilbert {
+            sort_hilbert(&mut nodes, |node| &node.rect);
+        }
+        let mut height = 0;
+        loop {
+            nodes = match packing {
+                Packing::Str => Node::pack_str(nodes, &self.opts),
+                Packing::Hilbert => Node::pack_in_order(nodes, &self.opts),
+            };
+            if nodes.len() == 1 {
+                break;
+            }
+            height += 1;
+        }
+        self.root = nodes.pop();
+        self.length = length;
+        self.height = height;
+    }
+    pub fn insert(&mut self, rect: Rect<D, C>, data: T) {
+        self.insert_item(rect, data);
+    }
+    /// Removes the first item within the rect that the predicate returns
+    /// true for. The data does not need to be comparable.
+    pub fn remove_by<F>(&mut self, rect: Rect<D, C>, pred: F) -> Option<(Rect<D, C>, T)>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.remove_item(rect, pred)
+    }
+    /// Removes every item that intersects the rect and that the predicate
+    /// returns true for, in a single pass over the tree. The items are
+    /// removed right away, even when the returned iterator is not used.
+    pub fn drain_in<F>(&mut self, rect: Rect<D, C>, pred: F) -> DrainIterator<D, C, T>
+    where
+        F: FnMut(&Rect<D, C>, &T) -> bool,
+    {
+        self.drain_where(Some(&rect), pred)
+    }
+    /// Keeps only the items that the predicate returns true for, in a single
+    /// pass over the tree.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&Rect<D, C>, &T) -> bool,
+    {
+        self.drain_where(None, |rect, data| !f(rect, data));
+    }
+    fn drain_where<F>(&mut self, rect: Option<&Rect<D, C>>, mut pred: F) -> DrainIterator<D, C, T>
+    where
+        F: FnMut(&Rect<D, C>, &T) -> bool,
+    {
+        let mut drained = Vec::new();
+        let mut reinsert = Vec::new();
+        if let Some(root) = &mut self.root {
+            let opts = &self.opts;
+            if !root.drain(
+                rect,
+                &mut pred,
+                &mut drained,
+                &mut reinsert,
+                self.height,
+                opts,
+            ) {
+                return DrainIterator {
+                    items: drained.into_iter(),
+                };
+            }
+            while self.height > 0 && root.len() == 1 {
+                *root = root.nodes_mut().pop().unwrap();
+                self.height -= 1;
+            }
+            if root.len() == 0 {
+                self.root = None;
+                self.height = 0;
+            }
+            self.length -= drained.len() + reinsert.len();
+            // condense once, with all orphaned items in Hilbert order
+            sort_hilbert(&mut reinsert, |item| &item.0);
+            for (rect, data) in reinsert {
+                self.insert(rect, data);
+            }
+        }
+        DrainIterator {
+            items: drained.into_iter(),
+        }
+    }
+}
+
+impl<const D: usize, C, T, S: InsertStrategy, K: Storage> RTree<D, C, T, S, K>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    pub fn options(&self) -> Options {
+        self.opts
+    }
+    pub fn strategy(&self) -> &S {
+        &self.strategy
     }
     pub fn len(&self) -> usize {
         self.length
     }
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+    #[allow(clippy::manual_map)]
     pub fn rect(&self) -> Option<Rect<D, C>> {
         match &self.root {
             Some(root) => Some(root.rect),
             None => None,
         }
     }
-    pub fn insert(&mut self, rect: Rect<D, C>, data: T) {
+    /// Checks the invariants of the tree, and returns the first one that is
+    /// broken. Every node rect is the union of its children, every node
+    /// other than the root is within the fanout, all leaves are at the
+    /// height of the tree, and the length is the number of items. Visits
+    /// every node, so it's meant for debugging and testing.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let count = match &self.root {
+            Some(root) if matches!(root.data, Data::Nodes(_)) => {
+                root.validate(self.height, &self.opts, &mut Vec::new())?
+            }
+            None if self.height == 0 => 0,
+            _ => {
+                return Err(ValidationError {
+                    path: Vec::new(),
+                    kind: Violation::Height,
+                })
+            }
+        };
+        if count != self.length {
+            return Err(ValidationError {
+                path: Vec::new(),
+                kind: Violation::Length(count),
+            });
+        }
+        Ok(())
+    }
+    /// Returns the quality metrics of the tree, which tell how well the
+    /// strategy and the fanout fit the data. Visits every node.
+    pub fn stats(&self) -> Stats<C> {
+        let mut stats = Stats::default();
+        if let Some(root) = &self.root {
+            stats.memory = root.stats(0, &self.opts, &mut stats.levels);
+        }
+        let mut fill = 0.0;
+        for level in &mut stats.levels {
+            fill += level.fill_factor;
+            level.fill_factor /= level.nodes as f64;
+            stats.nodes += level.nodes;
+            stats.overlap = stats.overlap + level.overlap;
+            stats.dead_space = stats.dead_space + level.dead_space;
+            stats.margin = stats.margin + level.margin;
+        }
+        if stats.nodes > 0 {
+            stats.fill_factor = fill / stats.nodes as f64;
+        }
+        stats
+    }
+    // Inserts and removes through the writer of the storage, which copies
+    // shared nodes for a PersistentRTree.
+    fn insert_item(&mut self, rect: Rect<D, C>, data: T)
+    where
+        K: Writer<Node<D, C, T, K>>,
+    {
+        let mut forced = ForcedReinsert::new();
+        let entry = Node {
+            rect,
+            data: Data::Item(data),
+        };
+        self.insert_entry(entry, 0, &mut forced);
+        while let Some((entry, level)) = forced.entries.pop() {
+            self.insert_entry(entry, level, &mut forced);
+        }
+        self.length += 1;
+    }
+    fn insert_entry(
+        &mut self,
+        entry: Node<D, C, T, K>,
+        level: usize,
+        forced: &mut ForcedReinsert<Node<D, C, T, K>>,
+    ) where
+        K: Writer<Node<D, C, T, K>>,
+    {
         if self.root.is_none() {
-            self.root = Some(Node::new(rect));
+            self.root = Some(Node::new(entry.rect, &self.opts));
         }
         let root = self.root.as_mut().unwrap();
-        root.insert(rect, data, self.height);
-        if root.len() == MAX_ITEMS {
-            let mut new_root = Node::new(root.rect);
-            let right = root.split_largest_axis_edge_snap();
+        root.insert(
+            entry,
+            level,
+            self.height,
+            &self.opts,
+            &self.strategy,
+            forced,
+        );
+        if root.len() == self.opts.max_items {
+            let mut new_root = Node::new(root.rect, &self.opts);
+            let right = root.split(&self.opts, &self.strategy);
             let left = self.root.take().unwrap();
             new_root.push(left);
             new_root.push(right);
             self.root = Some(new_root);
             self.height += 1;
         }
-        self.length += 1;
     }
+    // The item is found before anything is written, so that a
+    // PersistentRTree copies nothing when there is no such item.
+    fn remove_item<F>(&mut self, rect: Rect<D, C>, mut pred: F) -> Option<(Rect<D, C>, T)>
+    where
+        K: Writer<Node<D, C, T, K>>,
+        F: FnMut(&T) -> bool,
+    {
+        let root = self.root.as_mut()?;
+        let mut path = Vec::with_capacity(self.height + 1);
+        if !root.find(&rect, &mut pred, self.height, &mut path) {
+            return None;
+        }
+        path.reverse();
+        let mut reinsert = Vec::new();
+        let (removed, _) = root.remove(&path, &mut reinsert, &self.opts);
+        self.length -= reinsert.len() + 1;
+        if self.length == 0 {
+            self.root = None;
+            self.height = 0;
+        } else if self.height > 0 && root.len() == 1 {
+            let n = root.nodes_mut().pop().unwrap();
+            self.height -= 1;
+            self.root = Some(n);
+        }
+        // reinsert in Hilbert order to keep the inserts local
+        sort_hilbert(&mut reinsert, |item| &item.0);
+        for (rect, data) in reinsert {
+            self.insert_item(rect, data);
+        }
+        Some(removed)
+    }
+    pub fn search_flat<'a>(&'a self, rect: Rect<D, C>, items: &mut Vec<(Rect<D, C>, &'a T)>) {
+        if let Some(root) = &self.root {
+            root.search_flat(&rect, items);
+        }
+    }
+}
+
+impl<const D: usize, C, T: PartialEq, S: InsertStrategy> RTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
     pub fn remove(&mut self, rect: Rect<D, C>, data: &T) -> Option<(Rect<D, C>, T)> {
+        self.remove_by(rect, |item| item == data)
+    }
+    /// Moves an item from the old rect to the new rect. When the new rect
+    /// fits inside of the leaf node that holds the item, then the item is
+    /// moved in place and only the nodes on its path are recalculated.
+    /// Otherwise, or when no item has exactly the old rect, the item is
+    /// removed and inserted again.
+    ///
+    /// Returns false when the item was not found.
+    pub fn update(&mut self, old_rect: Rect<D, C>, data: &T, new_rect: Rect<D, C>) -> bool {
         if let Some(root) = &mut self.root {
-            let mut reinsert = Vec::new();
-            let (removed, recalced) = root.remove(&rect, data, &mut reinsert, self.height);
-            if removed.is_none() {
-                return None;
-            }
-            self.length -= reinsert.len() + 1;
-            if self.length == 0 {
-                self.root = None;
-            } else if self.height > 0 && root.len() == 1 {
-                let nodes = root.nodes_mut();
-                let mut n = nodes.pop().unwrap();
-                n.recalc();
-                self.height -= 1;
-                self.root = Some(n);
-            } else if recalced {
-                if let Some(root) = &mut self.root {
-                    root.recalc();
-                }
+            if let Update::Moved(_) = root.update(&old_rect, data, &new_rect, self.height) {
+                return true;
             }
-            while let Some(item) = reinsert.pop() {
-                self.insert(item.0, item.1);
+        }
+        match self.remove(old_rect, data) {
+            Some((_, data)) => {
+                self.insert(new_rect, data);
+                true
             }
-            removed
-        } else {
-            None
+            None => false,
         }
     }
-    pub fn search_flat<'a>(&'a self, rect: Rect<D, C>, items: &mut Vec<(Rect<D, C>, &'a T)>) {
-        if let Some(root) = &self.root {
-            root.search_flat(&rect, items);
+}
+
+impl<const D: usize, C, T, S: InsertStrategy> Extend<(Rect<D, C>, T)> for RTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    // An empty tree is bulk loaded when there are enough items to fill
+    // more than one node.
+    fn extend<I: IntoIterator<Item = (Rect<D, C>, T)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        if self.is_empty() && iter.size_hint().0 >= self.opts.max_items {
+            self.load(iter.collect(), Packing::Str);
+            return;
+        }
+        for (rect, data) in iter {
+            self.insert(rect, data);
+        }
+    }
+}
+
+impl<const D: usize, C, T, S: InsertStrategy + Default> FromIterator<(Rect<D, C>, T)>
+    for RTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    fn from_iter<I: IntoIterator<Item = (Rect<D, C>, T)>>(iter: I) -> RTree<D, C, T, S> {
+        let items: Vec<(Rect<D, C>, T)> = iter.into_iter().collect();
+        let mut tr = RTree::default();
+        tr.extend(items);
+        tr
+    }
+}
+
+impl<const D: usize, C, T, S> IntoIterator for RTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Default,
+{
+    type Item = (Rect<D, C>, T);
+    type IntoIter = IntoIter<D, C, T>;
+    fn into_iter(self) -> IntoIter<D, C, T> {
+        let mut stack = Vec::with_capacity(self.height + 1);
+        if let Some(root) = self.root {
+            stack.push(vec![root].into_iter());
+        }
+        IntoIter {
+            stack,
+            length: self.length,
         }
     }
 }
 
 // iterartors, ScanIterator, SearcIterator, NearbyIterator
 
-pub struct IterItem<'a, const D: usize, C: Default, T> {
+#[derive(Debug)]
+pub struct IterItem<'a, const D: usize, C: Default, T: ?Sized> {
     pub rect: Rect<D, C>,
     pub data: &'a T,
     pub dist: C,
 }
 
-impl<const D: usize, C, T: PartialEq> RTree<D, C, T>
+impl<const D: usize, C, T, S, K: Storage> RTree<D, C, T, S, K>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
 {
-    pub fn iter(&self) -> ScanIterator<D, C, T> {
+    #[allow(mismatched_lifetime_syntaxes)]
+    pub fn iter(&self) -> ScanIterator<D, C, T, K> {
         self.scan()
     }
 
-    pub fn scan(&self) -> ScanIterator<D, C, T> {
+    #[allow(mismatched_lifetime_syntaxes)]
+    pub fn scan(&self) -> ScanIterator<D, C, T, K> {
         ScanIterator::new(&self.root, self.height)
     }
 
-    pub fn search<'a>(&self, rect: Rect<D, C>) -> SearchIterator<D, C, T> {
-        SearchIterator::new(&self.root, self.height, rect)
+    pub fn search(&self, rect: Rect<D, C>) -> SearchIterator<'_, D, C, T, K> {
+        SearchWithIterator::new(&self.root, self.height, Intersects(rect))
+    }
+
+    /// Returns the items that are completely inside of the rect.
+    pub fn search_within(&self, rect: Rect<D, C>) -> SearchWithinIterator<'_, D, C, T, K> {
+        SearchWithIterator::new(&self.root, self.height, Within(rect))
+    }
+
+    /// Returns the items that completely contain the rect, such as the
+    /// items whose bounds contain a point.
+    pub fn search_containing(&self, rect: Rect<D, C>) -> SearchContainingIterator<'_, D, C, T, K> {
+        SearchWithIterator::new(&self.root, self.height, Contains(rect))
+    }
+
+    /// Returns the items that match the predicate, and only visits the
+    /// nodes that may hold them.
+    pub fn search_with<P>(&self, pred: P) -> SearchWithIterator<'_, D, C, T, P, K>
+    where
+        P: SpatialPredicate<D, C, T>,
+    {
+        SearchWithIterator::new(&self.root, self.height, pred)
+    }
+
+    /// Returns the items that the line segment from `a` to `b` passes
+    /// through, see [`Segment`].
+    pub fn search_segment(&self, a: [C; D], b: [C; D]) -> SearchSegmentIterator<'_, D, C, T, K> {
+        SearchWithIterator::new(&self.root, self.height, Segment { a, b })
+    }
+
+    /// Returns the items that are within a distance of the center, with
+    /// the squared distance, as of [`Rect::box_dist`], in
+    /// [`IterItem::dist`]. The items are not in order of distance, and
+    /// there are none for a negative radius.
+    pub fn search_radius(&self, center: [C; D], radius: C) -> SearchRadiusIterator<'_, D, C, T, K> {
+        // squaring would turn a negative radius positive
+        let stack = if radius < C::default() {
+            Vec::new()
+        } else {
+            StackNode::new_stack(&self.root, self.height)
+        };
+        SearchRadiusIterator {
+            stack,
+            center: Rect {
+                min: center,
+                max: center,
+            },
+            max_dist: radius * radius,
+        }
     }
 
-    pub fn nearby<'a, F>(&'a self, dist: F) -> NearbyIterator<D, C, T, F>
+    #[allow(mismatched_lifetime_syntaxes)]
+    pub fn nearby<'a, F>(&'a self, dist: F) -> NearbyIterator<D, C, T, F, K>
     where
         F: FnMut(Rect<D, C>, Option<&'a T>) -> C,
     {
@@ -505,19 +1776,91 @@ where
     }
 }
 
-struct StackNode<'a, const D: usize, C, T>
+impl<const D: usize, C: From<f32>, T, S, K: Storage> RTree<D, C, T, S, K>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Div<Output = C> + Default,
+{
+    /// Returns the items that the ray from the origin hits, in order of
+    /// where the ray enters them, up to `max_t` times the direction. The
+    /// entry is in [`IterItem::dist`] as a multiple of the direction, and is
+    /// zero for items that contain the origin. The direction does not need
+    /// to be a unit vector.
+    ///
+    /// Only the nodes that are needed for the next hit are visited, so
+    /// taking the first hit is cheap.
+    ///
+    /// The coordinates must be floats, which the `From<f32>` bound ensures,
+    /// because the entries are found by division. For integer coordinates,
+    /// [`search_segment`](RTree::search_segment) is exact but unordered.
+    pub fn raycast(
+        &self,
+        origin: [C; D],
+        dir: [C; D],
+        max_t: C,
+    ) -> RaycastIterator<'_, D, C, T, K> {
+        let mut queue = Queue::new();
+        if let Some(root) = &self.root {
+            if let Some(dist) = root.rect.ray_entry(&origin, &dir, max_t) {
+                queue.push(NearbyItem { dist, node: root });
+            }
+        }
+        RaycastIterator {
+            queue,
+            origin,
+            dir,
+            max_t,
+        }
+    }
+}
+
+// mutable iterators, ScanIteratorMut, SearchIteratorMut, NearbyIteratorMut
+
+#[derive(Debug)]
+pub struct IterItemMut<'a, const D: usize, C: Default, T> {
+    pub rect: Rect<D, C>,
+    pub data: &'a mut T,
+    pub dist: C,
+}
+
+impl<const D: usize, C, T, S> RTree<D, C, T, S>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
 {
-    nodes: &'a [Node<D, C, T>],
+    /// Like [`RTree::iter`], but with mutable access to the data.
+    pub fn iter_mut(&mut self) -> ScanIteratorMut<'_, D, C, T> {
+        ScanIteratorMut::new(&mut self.root, self.height)
+    }
+
+    /// Like [`RTree::search`], but with mutable access to the data.
+    pub fn search_mut(&mut self, rect: Rect<D, C>) -> SearchIteratorMut<'_, D, C, T> {
+        SearchIteratorMut::new(&mut self.root, self.height, rect)
+    }
+
+    /// Like [`RTree::nearby`], but with mutable access to the data.
+    pub fn nearby_mut<F>(&mut self, dist: F) -> NearbyIteratorMut<'_, D, C, T, F>
+    where
+        F: FnMut(Rect<D, C>, Option<&T>) -> C,
+    {
+        NearbyIteratorMut::new(&mut self.root, dist)
+    }
+}
+
+struct StackNode<'a, const D: usize, C, T, K: Storage = Owned>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
+{
+    nodes: &'a [Node<D, C, T, K>],
     index: usize,
 }
 
-impl<'a, const D: usize, C, T> StackNode<'a, D, C, T>
+impl<'a, const D: usize, C, T, K: Storage> StackNode<'a, D, C, T, K>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
 {
-    fn new_stack(root: &'a Option<Node<D, C, T>>, height: usize) -> Vec<StackNode<'a, D, C, T>> {
+    fn new_stack(
+        root: &'a Option<Node<D, C, T, K>>,
+        height: usize,
+    ) -> Vec<StackNode<'a, D, C, T, K>> {
         let mut stack = Vec::with_capacity(height + 1);
         if let Some(root) = &root {
             stack.push(StackNode {
@@ -534,29 +1877,31 @@ where
 
 // scan iterator
 
-pub struct ScanIterator<'a, const D: usize, C, T>
+/// Iterates over every item. `Send` and `Sync` when `C` and `T` are `Sync`.
+pub struct ScanIterator<'a, const D: usize, C, T, K: Storage = Owned>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
 {
-    stack: Vec<StackNode<'a, D, C, T>>,
+    stack: Vec<StackNode<'a, D, C, T, K>>,
 }
 
-impl<'a, const D: usize, C, T> ScanIterator<'a, D, C, T>
+impl<'a, const D: usize, C, T, K: Storage> ScanIterator<'a, D, C, T, K>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
 {
-    fn new(root: &'a Option<Node<D, C, T>>, height: usize) -> ScanIterator<'a, D, C, T> {
+    fn new(root: &'a Option<Node<D, C, T, K>>, height: usize) -> ScanIterator<'a, D, C, T, K> {
         ScanIterator {
             stack: StackNode::new_stack(root, height),
         }
     }
 }
 
-impl<'a, const D: usize, C, T> Iterator for ScanIterator<'a, D, C, T>
+impl<'a, const D: usize, C, T, K: Storage> Iterator for ScanIterator<'a, D, C, T, K>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
 {
     type Item = IterItem<'a, D, C, T>;
+    #[allow(clippy::never_loop)]
     fn next(&mut self) -> Option<Self::Item> {
         'outer: while let Some(stack) = &mut self.stack.last_mut() {
             for i in stack.index..stack.nodes.len() {
@@ -584,60 +1929,130 @@ where
     }
 }
 
-// search iterator -- much like the scan iterator but with a intersects guard.
+// search iterator -- much like the scan iterator but with the guards of a
+// predicate.
 
-pub struct SearchIterator<'a, const D: usize, C, T>
+/// Iterates over the items that match a predicate. `Send` when `C` and `T`
+/// are `Sync` and `P` is `Send`, and `Sync` when `P` is `Sync` instead.
+pub struct SearchWithIterator<'a, const D: usize, C, T, P, K: Storage = Owned>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
 {
-    stack: Vec<StackNode<'a, D, C, T>>,
-    rect: Rect<D, C>,
+    stack: Vec<StackNode<'a, D, C, T, K>>,
+    pred: P,
 }
 
-impl<'a, const D: usize, C, T> SearchIterator<'a, D, C, T>
+/// Iterates over the items that intersect a rect.
+pub type SearchIterator<'a, const D: usize, C, T, K = Owned> =
+    SearchWithIterator<'a, D, C, T, Intersects<D, C>, K>;
+
+/// Iterates over the items that are inside of a rect.
+pub type SearchWithinIterator<'a, const D: usize, C, T, K = Owned> =
+    SearchWithIterator<'a, D, C, T, Within<D, C>, K>;
+
+/// Iterates over the items that a line segment passes through.
+pub type SearchSegmentIterator<'a, const D: usize, C, T, K = Owned> =
+    SearchWithIterator<'a, D, C, T, Segment<D, C>, K>;
+
+/// Iterates over the items that contain a rect.
+pub type SearchContainingIterator<'a, const D: usize, C, T, K = Owned> =
+    SearchWithIterator<'a, D, C, T, Contains<D, C>, K>;
+
+impl<'a, const D: usize, C, T, P, K: Storage> SearchWithIterator<'a, D, C, T, P, K>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
 {
     fn new(
-        root: &'a Option<Node<D, C, T>>,
+        root: &'a Option<Node<D, C, T, K>>,
         height: usize,
-        rect: Rect<D, C>,
-    ) -> SearchIterator<'a, D, C, T> {
-        SearchIterator {
+        pred: P,
+    ) -> SearchWithIterator<'a, D, C, T, P, K> {
+        SearchWithIterator {
             stack: StackNode::new_stack(root, height),
-            rect,
+            pred,
         }
     }
 }
 
-impl<'a, const D: usize, C, T> Iterator for SearchIterator<'a, D, C, T>
+impl<'a, const D: usize, C, T, P, K: Storage> Iterator for SearchWithIterator<'a, D, C, T, P, K>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    P: SpatialPredicate<D, C, T>,
 {
     type Item = IterItem<'a, D, C, T>;
     fn next(&mut self) -> Option<Self::Item> {
         'outer: while let Some(stack) = &mut self.stack.last_mut() {
             for i in stack.index..stack.nodes.len() {
-                if !stack.nodes[i].rect.intersects(&self.rect) {
+                let node = &stack.nodes[i];
+                match &node.data {
+                    Data::Item(data) => {
+                        if !self.pred.item_matches(&node.rect, data) {
+                            continue;
+                        }
+                        stack.index = i + 1;
+                        return Some(IterItem {
+                            rect: node.rect,
+                            data,
+                            dist: Default::default(),
+                        });
+                    }
+                    Data::Nodes(nodes) => {
+                        if !self.pred.node_may_match(&node.rect) {
+                            continue;
+                        }
+                        stack.index = i + 1;
+                        self.stack.push(StackNode { nodes, index: 0 });
+                        continue 'outer;
+                    }
+                }
+            }
+            self.stack.pop();
+        }
+        None
+    }
+}
+
+// search radius iterator -- prunes the nodes that are farther from the
+// center than the radius, comparing the squared distances.
+
+/// Iterates over the items that are within a distance of a point. `Send`
+/// and `Sync` when `C` and `T` are `Sync`.
+pub struct SearchRadiusIterator<'a, const D: usize, C, T, K: Storage = Owned>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
+{
+    stack: Vec<StackNode<'a, D, C, T, K>>,
+    center: Rect<D, C>,
+    max_dist: C,
+}
+
+impl<'a, const D: usize, C, T, K: Storage> Iterator for SearchRadiusIterator<'a, D, C, T, K>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    type Item = IterItem<'a, D, C, T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        'outer: while let Some(stack) = &mut self.stack.last_mut() {
+            for i in stack.index..stack.nodes.len() {
+                let node = &stack.nodes[i];
+                let dist = node.rect.box_dist(&self.center);
+                if dist > self.max_dist {
                     continue;
                 }
                 stack.index = i + 1;
-                if let Data::Item(data) = &stack.nodes[i].data {
-                    return Some(IterItem {
-                        rect: stack.nodes[i].rect,
-                        data,
-                        dist: Default::default(),
-                    });
+                match &node.data {
+                    Data::Item(data) => {
+                        return Some(IterItem {
+                            rect: node.rect,
+                            data,
+                            dist,
+                        });
+                    }
+                    Data::Nodes(nodes) => {
+                        self.stack.push(StackNode { nodes, index: 0 });
+                        continue 'outer;
+                    }
                 }
-                let snode = StackNode {
-                    nodes: match &stack.nodes[i].data {
-                        Data::Nodes(nodes) => nodes,
-                        _ => unreachable!(),
-                    },
-                    index: 0,
-                };
-                self.stack.push(snode);
-                continue 'outer;
             }
             self.stack.pop();
         }
@@ -645,46 +2060,48 @@ where
     }
 }
 
-struct NearbyItem<'a, const D: usize, C, T>
+struct NearbyItem<'a, const D: usize, C, T, K: Storage = Owned>
 where
     C: PartialOrd + Copy + Default,
 {
     dist: C,
-    node: &'a Node<D, C, T>,
+    node: &'a Node<D, C, T, K>,
 }
 
-impl<'a, const D: usize, C, T> PartialEq for NearbyItem<'a, D, C, T>
+impl<'a, const D: usize, C, T, K: Storage> PartialEq for NearbyItem<'a, D, C, T, K>
 where
     C: PartialOrd + Copy + Default,
 {
-    fn eq(&self, other: &NearbyItem<'a, D, C, T>) -> bool {
+    fn eq(&self, other: &NearbyItem<'a, D, C, T, K>) -> bool {
         self.dist.eq(&other.dist)
     }
 }
 
-impl<'a, const D: usize, C, T> PartialOrd for NearbyItem<'a, D, C, T>
+impl<'a, const D: usize, C, T, K: Storage> PartialOrd for NearbyItem<'a, D, C, T, K>
 where
     C: PartialOrd + Copy + Default,
 {
-    fn partial_cmp(&self, other: &NearbyItem<'a, D, C, T>) -> Option<Ordering> {
+    fn partial_cmp(&self, other: &NearbyItem<'a, D, C, T, K>) -> Option<Ordering> {
         self.dist.partial_cmp(&other.dist)
     }
 }
 
-pub struct NearbyIterator<'a, const D: usize, C, T, F>
+/// Iterates over the items in order of distance. `Send` when `C` and `T`
+/// are `Sync` and `F` is `Send`, and `Sync` when `F` is `Sync` instead.
+pub struct NearbyIterator<'a, const D: usize, C, T, F, K: Storage = Owned>
 where
     C: PartialOrd + Copy + Default,
 {
-    queue: Queue<NearbyItem<'a, D, C, T>>,
+    queue: Queue<NearbyItem<'a, D, C, T, K>>,
     dist: F,
 }
 
-impl<'a, const D: usize, C, T, F> NearbyIterator<'a, D, C, T, F>
+impl<'a, const D: usize, C, T, F, K: Storage> NearbyIterator<'a, D, C, T, F, K>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
     F: FnMut(Rect<D, C>, Option<&'a T>) -> C,
 {
-    fn new(root: &'a Option<Node<D, C, T>>, dist: F) -> NearbyIterator<'a, D, C, T, F> {
+    fn new(root: &'a Option<Node<D, C, T, K>>, dist: F) -> NearbyIterator<'a, D, C, T, F, K> {
         let mut queue = Queue::new();
         if let Some(root) = root {
             queue.push(NearbyItem {
@@ -696,12 +2113,13 @@ where
     }
 }
 
-impl<'a, const D: usize, C, T, F> Iterator for NearbyIterator<'a, D, C, T, F>
+impl<'a, const D: usize, C, T, F, K: Storage> Iterator for NearbyIterator<'a, D, C, T, F, K>
 where
     C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
     F: FnMut(Rect<D, C>, Option<&'a T>) -> C,
 {
     type Item = IterItem<'a, D, C, T>;
+    #[allow(clippy::redundant_field_names)]
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(item) = self.queue.pop() {
             match &item.node.data {
@@ -731,3 +2149,332 @@ where
         None
     }
 }
+
+// raycast iterator -- the nearby iterator, where the distance is where the
+// ray enters the rect. A child is never entered before its parent.
+
+/// Iterates over the items that a ray hits, in order. `Send` and `Sync`
+/// when `C` and `T` are `Sync`.
+pub struct RaycastIterator<'a, const D: usize, C, T, K: Storage = Owned>
+where
+    C: PartialOrd + Copy + Default,
+{
+    queue: Queue<NearbyItem<'a, D, C, T, K>>,
+    origin: [C; D],
+    dir: [C; D],
+    max_t: C,
+}
+
+impl<'a, const D: usize, C: From<f32>, T, K: Storage> Iterator for RaycastIterator<'a, D, C, T, K>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Div<Output = C> + Default,
+{
+    type Item = IterItem<'a, D, C, T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(item) = self.queue.pop() {
+            match &item.node.data {
+                Data::Item(data) => {
+                    return Some(IterItem {
+                        rect: item.node.rect,
+                        data,
+                        dist: item.dist,
+                    });
+                }
+                Data::Nodes(nodes) => {
+                    for i in 0..nodes.len() {
+                        let entry = nodes[i].rect.ray_entry(&self.origin, &self.dir, self.max_t);
+                        if let Some(dist) = entry {
+                            self.queue.push(NearbyItem {
+                                dist,
+                                node: &nodes[i],
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+// mutable scan iterator -- the stack holds the remaining children of each
+// level, which hands out each node only once.
+
+fn new_stack_mut<'a, const D: usize, C, T>(
+    root: &'a mut Option<Node<D, C, T>>,
+    height: usize,
+) -> Vec<std::slice::IterMut<'a, Node<D, C, T>>>
+where
+    C: PartialOrd + Copy + Default,
+{
+    let mut stack = Vec::with_capacity(height + 1);
+    if let Some(root) = root {
+        stack.push(match &mut root.data {
+            Data::Nodes(nodes) => nodes.iter_mut(),
+            _ => unreachable!(),
+        });
+    }
+    stack
+}
+
+/// Iterates over every item for writing. `Send` when `C` and `T` are
+/// `Send`, and `Sync` when they are `Sync`.
+pub struct ScanIteratorMut<'a, const D: usize, C, T>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
+{
+    stack: Vec<std::slice::IterMut<'a, Node<D, C, T>>>,
+}
+
+impl<'a, const D: usize, C, T> ScanIteratorMut<'a, D, C, T>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
+{
+    fn new(root: &'a mut Option<Node<D, C, T>>, height: usize) -> ScanIteratorMut<'a, D, C, T> {
+        ScanIteratorMut {
+            stack: new_stack_mut(root, height),
+        }
+    }
+}
+
+impl<'a, const D: usize, C, T> Iterator for ScanIteratorMut<'a, D, C, T>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
+{
+    type Item = IterItemMut<'a, D, C, T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(stack) = self.stack.last_mut() {
+            let node = match stack.next() {
+                Some(node) => node,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            match &mut node.data {
+                Data::Item(data) => {
+                    return Some(IterItemMut {
+                        rect: node.rect,
+                        data,
+                        dist: Default::default(),
+                    });
+                }
+                Data::Nodes(nodes) => self.stack.push(nodes.iter_mut()),
+            }
+        }
+        None
+    }
+}
+
+// mutable search iterator
+
+/// Iterates over the items that intersect a rect for writing. `Send` when
+/// `C` and `T` are `Send`, and `Sync` when they are `Sync`.
+pub struct SearchIteratorMut<'a, const D: usize, C, T>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
+{
+    stack: Vec<std::slice::IterMut<'a, Node<D, C, T>>>,
+    rect: Rect<D, C>,
+}
+
+impl<'a, const D: usize, C, T> SearchIteratorMut<'a, D, C, T>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
+{
+    fn new(
+        root: &'a mut Option<Node<D, C, T>>,
+        height: usize,
+        rect: Rect<D, C>,
+    ) -> SearchIteratorMut<'a, D, C, T> {
+        SearchIteratorMut {
+            stack: new_stack_mut(root, height),
+            rect,
+        }
+    }
+}
+
+impl<'a, const D: usize, C, T> Iterator for SearchIteratorMut<'a, D, C, T>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    type Item = IterItemMut<'a, D, C, T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(stack) = self.stack.last_mut() {
+            let node = match stack.find(|node| node.rect.intersects(&self.rect)) {
+                Some(node) => node,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            match &mut node.data {
+                Data::Item(data) => {
+                    return Some(IterItemMut {
+                        rect: node.rect,
+                        data,
+                        dist: Default::default(),
+                    });
+                }
+                Data::Nodes(nodes) => self.stack.push(nodes.iter_mut()),
+            }
+        }
+        None
+    }
+}
+
+// mutable nearby iterator
+
+struct NearbyItemMut<'a, const D: usize, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    dist: C,
+    node: &'a mut Node<D, C, T>,
+}
+
+impl<'a, const D: usize, C, T> PartialEq for NearbyItemMut<'a, D, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    fn eq(&self, other: &NearbyItemMut<'a, D, C, T>) -> bool {
+        self.dist.eq(&other.dist)
+    }
+}
+
+impl<'a, const D: usize, C, T> PartialOrd for NearbyItemMut<'a, D, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    fn partial_cmp(&self, other: &NearbyItemMut<'a, D, C, T>) -> Option<Ordering> {
+        self.dist.partial_cmp(&other.dist)
+    }
+}
+
+/// Iterates over the items in order of distance for writing. `Send` when
+/// `C`, `T` and `F` are `Send`, and `Sync` when they are `Sync`.
+pub struct NearbyIteratorMut<'a, const D: usize, C, T, F>
+where
+    C: PartialOrd + Copy + Default,
+{
+    queue: Queue<NearbyItemMut<'a, D, C, T>>,
+    dist: F,
+}
+
+impl<'a, const D: usize, C, T, F> NearbyIteratorMut<'a, D, C, T, F>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
+    F: FnMut(Rect<D, C>, Option<&T>) -> C,
+{
+    fn new(root: &'a mut Option<Node<D, C, T>>, dist: F) -> NearbyIteratorMut<'a, D, C, T, F> {
+        let mut queue = Queue::new();
+        if let Some(root) = root {
+            queue.push(NearbyItemMut {
+                dist: Default::default(),
+                node: root,
+            });
+        }
+        NearbyIteratorMut { queue, dist }
+    }
+}
+
+impl<'a, const D: usize, C, T, F> Iterator for NearbyIteratorMut<'a, D, C, T, F>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
+    F: FnMut(Rect<D, C>, Option<&T>) -> C,
+{
+    type Item = IterItemMut<'a, D, C, T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(item) = self.queue.pop() {
+            match &mut item.node.data {
+                Data::Item(data) => {
+                    return Some(IterItemMut {
+                        rect: item.node.rect,
+                        data,
+                        dist: item.dist,
+                    });
+                }
+                Data::Nodes(nodes) => {
+                    for node in nodes.iter_mut() {
+                        let dist = (self.dist)(
+                            node.rect,
+                            match &node.data {
+                                Data::Item(data) => Some(data),
+                                _ => None,
+                            },
+                        );
+                        self.queue.push(NearbyItemMut { dist, node });
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The items that were removed by [`RTree::drain_in`]. `Send` when `C` and
+/// `T` are `Send`, and `Sync` when they are `Sync`.
+pub struct DrainIterator<const D: usize, C, T>
+where
+    C: Default,
+{
+    items: std::vec::IntoIter<(Rect<D, C>, T)>,
+}
+
+impl<const D: usize, C, T> Iterator for DrainIterator<D, C, T>
+where
+    C: Default,
+{
+    type Item = (Rect<D, C>, T);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.items.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.items.size_hint()
+    }
+}
+
+/// An iterator that moves the items out of an RTree. The nodes are freed
+/// along the way. `Send` when `C` and `T` are `Send`, and `Sync` when they
+/// are `Sync`.
+pub struct IntoIter<const D: usize, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    stack: Vec<std::vec::IntoIter<Node<D, C, T>>>,
+    length: usize,
+}
+
+impl<const D: usize, C, T> Iterator for IntoIter<D, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    type Item = (Rect<D, C>, T);
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(stack) = self.stack.last_mut() {
+            let node = match stack.next() {
+                Some(node) => node,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            match node.data {
+                Data::Item(data) => {
+                    self.length -= 1;
+                    return Some((node.rect, data));
+                }
+                Data::Nodes(nodes) => self.stack.push(nodes.into_iter()),
+            }
+        }
+        None
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+}
+
+impl<const D: usize, C, T> ExactSizeIterator for IntoIter<D, C, T> where
+    C: PartialOrd + Copy + Default
+{
+}
@@ -0,0 +1,555 @@
+//! A flat binary format for trees, and a read-only [`MappedRTree`] that
+//! searches the bytes in place, such as a memory mapped file.
+//!
+//! The layout is little endian and has no padding.
+//!
+//! ```text
+//! header      magic "RTRF", version: u32, dims: u32, coord size: u32,
+//!             height: u32, reserved: u32, nodes: u64, entries: u64,
+//!             items: u64, data size: u64, root rect
+//! nodes       first entry: u64, entry count: u64        (per node)
+//! rects       min coords, max coords                    (per entry)
+//! refs        node index, or item index at the leaves   (per entry)
+//! offsets     data offset: u64                          (per item, plus one)
+//! data        the encoded items
+//! ```
+//!
+//! The nodes are stored in breadth first order, starting with the root, and
+//! the children of every node are contiguous entries. The data of item `i`
+//! is found at `offsets[i]..offsets[i+1]` of the data section.
+
+use super::{Data, IterItem, Node, RTree, Rect};
+use pqueue::Queue;
+use std::cmp::Ordering;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::ops::{Add, Mul, Sub};
+
+const MAGIC: &[u8; 4] = b"RTRF";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 56;
+
+/// A value with a fixed size binary encoding, such as a coordinate.
+pub trait Codec: Sized {
+    /// The number of bytes of every encoded value.
+    const SIZE: usize;
+    /// Encodes the value into exactly `SIZE` bytes.
+    fn encode(&self, buf: &mut [u8]);
+    /// Decodes a value from exactly `SIZE` bytes.
+    fn decode(buf: &[u8]) -> Self;
+}
+
+macro_rules! impl_codec {
+    ($($t:ty),*) => {
+        $(
+            impl Codec for $t {
+                const SIZE: usize = std::mem::size_of::<$t>();
+                fn encode(&self, buf: &mut [u8]) {
+                    buf.copy_from_slice(&self.to_le_bytes());
+                }
+                fn decode(buf: &[u8]) -> $t {
+                    <$t>::from_le_bytes(buf.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_codec!(i8, i16, i32, i64, i128, u8, u16, u32, u64, u128, f32, f64);
+
+pub(crate) fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub(crate) fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+pub(crate) fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+pub(crate) fn rect_size<const D: usize, C: Codec>() -> usize {
+    D * 2 * C::SIZE
+}
+
+pub(crate) fn encode_rect<const D: usize, C>(rect: &Rect<D, C>, out: &mut Vec<u8>)
+where
+    C: Codec + Default,
+{
+    for coord in rect.min.iter().chain(rect.max.iter()) {
+        let start = out.len();
+        out.resize(start + C::SIZE, 0);
+        coord.encode(&mut out[start..]);
+    }
+}
+
+impl<const D: usize, C, T, S> RTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Codec + Default,
+{
+    /// Writes the tree in the flat format of [`MappedRTree`]. The encode
+    /// function appends the bytes of an item to the buffer.
+    pub fn write_mapped<W, F>(&self, w: &mut W, mut encode: F) -> io::Result<()>
+    where
+        W: Write,
+        F: FnMut(&T, &mut Vec<u8>),
+    {
+        let mut nodes = Vec::new();
+        let mut rects = Vec::new();
+        let mut refs = Vec::new();
+        let mut offsets = Vec::new();
+        let mut data = Vec::new();
+        // breadth first, where a node's children are queued as they are
+        // given an index
+        let mut queue: Vec<&Node<D, C, T>> = self.root.iter().collect();
+        let mut entries = 0;
+        let mut k = 0;
+        while k < queue.len() {
+            let children = queue[k].nodes();
+            nodes.extend_from_slice(&(entries as u64).to_le_bytes());
+            nodes.extend_from_slice(&(children.len() as u64).to_le_bytes());
+            for child in children.iter() {
+                encode_rect(&child.rect, &mut rects);
+                let index = match &child.data {
+                    Data::Item(item) => {
+                        offsets.extend_from_slice(&(data.len() as u64).to_le_bytes());
+                        encode(item, &mut data);
+                        offsets.len() / 8 - 1
+                    }
+                    Data::Nodes(_) => {
+                        queue.push(child);
+                        queue.len() - 1
+                    }
+                };
+                refs.extend_from_slice(&(index as u64).to_le_bytes());
+            }
+            entries += children.len();
+            k += 1;
+        }
+        let items = offsets.len() / 8;
+        offsets.extend_from_slice(&(data.len() as u64).to_le_bytes());
+
+        let mut header = Vec::with_capacity(HEADER_SIZE + rect_size::<D, C>());
+        header.extend_from_slice(MAGIC);
+        for n in [VERSION, D as u32, C::SIZE as u32, self.height as u32, 0] {
+            header.extend_from_slice(&n.to_le_bytes());
+        }
+        for n in [queue.len(), entries, items, data.len()] {
+            header.extend_from_slice(&(n as u64).to_le_bytes());
+        }
+        match &self.root {
+            Some(root) => encode_rect(&root.rect, &mut header),
+            None => header.resize(HEADER_SIZE + rect_size::<D, C>(), 0),
+        }
+        for section in [header, nodes, rects, refs, offsets, data] {
+            w.write_all(&section)?;
+        }
+        Ok(())
+    }
+}
+
+/// A read-only tree over bytes in the flat format, which are written with
+/// [`RTree::write_mapped`]. Nothing is copied or decoded up front, and the
+/// items are the encoded bytes.
+///
+/// Every node, reference and data offset is checked once when opened, in
+/// O(n), so that searching a tree never reads outside of the bytes. The
+/// rects are not checked, and a corrupt rect only gives wrong results.
+pub struct MappedRTree<'a, const D: usize, C>
+where
+    C: PartialOrd + Copy + Default,
+{
+    buf: &'a [u8],
+    height: usize,
+    len: usize,
+    rect: Option<Rect<D, C>>,
+    nodes: usize,
+    rects: usize,
+    refs: usize,
+    offsets: usize,
+    data: usize,
+    _coord: PhantomData<C>,
+}
+
+impl<'a, const D: usize, C> Clone for MappedRTree<'a, D, C>
+where
+    C: PartialOrd + Copy + Default,
+{
+    fn clone(&self) -> MappedRTree<'a, D, C> {
+        *self
+    }
+}
+
+impl<'a, const D: usize, C> Copy for MappedRTree<'a, D, C> where C: PartialOrd + Copy + Default {}
+
+impl<'a, const D: usize, C> MappedRTree<'a, D, C>
+where
+    C: PartialOrd + Copy + Codec + Default,
+{
+    /// Opens the tree in the bytes. Fails with `InvalidData` when the bytes
+    /// are not a tree of the same version, dimensions and coordinate size,
+    /// or when they are truncated or corrupt.
+    pub fn open(buf: &'a [u8]) -> io::Result<MappedRTree<'a, D, C>> {
+        let rsize = rect_size::<D, C>();
+        if buf.len() < HEADER_SIZE + rsize || &buf[..4] != MAGIC {
+            return Err(invalid("not a mapped rtree"));
+        }
+        if read_u32(buf, 4) != VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        if read_u32(buf, 8) as usize != D || read_u32(buf, 12) as usize != C::SIZE {
+            return Err(invalid("dimensions or coordinate size do not match"));
+        }
+        let height = read_u32(buf, 16) as usize;
+        let counts = [24, 32, 40, 48].map(|offset| usize::try_from(read_u64(buf, offset)));
+        let [Ok(nodes), Ok(entries), Ok(items), Ok(data)] = counts else {
+            return Err(invalid("section too large"));
+        };
+        // the sections follow each other, and must end with the bytes
+        let mut offset = HEADER_SIZE + rsize;
+        let mut sections = [0; 5];
+        let sizes = [
+            (nodes, 16),
+            (entries, rsize),
+            (entries, 8),
+            (items, 8),
+            (data, 1),
+        ];
+        for (i, (count, size)) in sizes.into_iter().enumerate() {
+            sections[i] = offset;
+            let count = if i == 3 {
+                count.checked_add(1)
+            } else {
+                Some(count)
+            };
+            offset = count
+                .and_then(|count| count.checked_mul(size))
+                .and_then(|size| size.checked_add(offset))
+                .ok_or_else(|| invalid("section too large"))?;
+        }
+        if offset != buf.len() {
+            return Err(invalid("size does not match the header"));
+        }
+        if (nodes == 0) != (items == 0) {
+            return Err(invalid("empty tree with nodes"));
+        }
+        let rect = if nodes == 0 {
+            None
+        } else {
+            Some(decode_rect(&buf[HEADER_SIZE..]))
+        };
+        let tr = MappedRTree {
+            buf,
+            height,
+            len: items,
+            rect,
+            nodes: sections[0],
+            rects: sections[1],
+            refs: sections[2],
+            offsets: sections[3],
+            data: sections[4],
+            _coord: PhantomData,
+        };
+        tr.check(nodes, entries, data)?;
+        Ok(tr)
+    }
+    // Checks that the nodes cover the entries in order, that every entry
+    // refers to a node or an item that exists, and that every item is in
+    // the data section. The nodes are walked from the root, and a node that
+    // is reached twice fails, so the walk ends after each node is visited
+    // once.
+    fn check(&self, nodes: usize, entries: usize, data: usize) -> io::Result<()> {
+        let mut end = 0;
+        for index in 0..nodes {
+            let offset = self.nodes + index * 16;
+            let count = read_u64(self.buf, offset + 8);
+            if read_u64(self.buf, offset) != end as u64 || count > (entries - end) as u64 {
+                return Err(invalid("node out of bounds"));
+            }
+            end += count as usize;
+        }
+        if end != entries {
+            return Err(invalid("node out of bounds"));
+        }
+        let mut start = 0;
+        for index in 0..=self.len {
+            let offset = read_u64(self.buf, self.offsets + index * 8);
+            if offset < start || offset > data as u64 {
+                return Err(invalid("item out of bounds"));
+            }
+            start = offset;
+        }
+        if nodes == 0 {
+            return Ok(());
+        }
+        let mut seen = vec![false; nodes];
+        seen[0] = true;
+        let mut level = vec![0];
+        let mut depth = 0;
+        let mut items = 0;
+        while !level.is_empty() {
+            let mut next = Vec::new();
+            for &index in &level {
+                for entry in self.node(index) {
+                    let child = read_u64(self.buf, self.refs + entry * 8);
+                    if depth == self.height {
+                        if child >= self.len as u64 {
+                            return Err(invalid("item out of bounds"));
+                        }
+                        items += 1;
+                    } else if child >= nodes as u64 || seen[child as usize] {
+                        return Err(invalid("node out of bounds"));
+                    } else {
+                        seen[child as usize] = true;
+                        next.push(child as usize);
+                    }
+                }
+            }
+            level = next;
+            depth += 1;
+        }
+        if items != self.len {
+            return Err(invalid("size does not match the header"));
+        }
+        Ok(())
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn rect(&self) -> Option<Rect<D, C>> {
+        self.rect
+    }
+    // the entries of a node
+    fn node(&self, index: usize) -> std::ops::Range<usize> {
+        let offset = self.nodes + index * 16;
+        let first = read_u64(self.buf, offset) as usize;
+        first..first + read_u64(self.buf, offset + 8) as usize
+    }
+    fn entry_rect(&self, entry: usize) -> Rect<D, C> {
+        let size = rect_size::<D, C>();
+        decode_rect(&self.buf[self.rects + entry * size..][..size])
+    }
+    fn entry_ref(&self, entry: usize) -> usize {
+        read_u64(self.buf, self.refs + entry * 8) as usize
+    }
+    fn item(&self, index: usize) -> &'a [u8] {
+        let offset = self.offsets + index * 8;
+        let start = read_u64(self.buf, offset) as usize;
+        let end = read_u64(self.buf, offset + 8) as usize;
+        &self.buf[self.data..][start..end]
+    }
+    pub fn iter(&self) -> ScanIterator<'a, D, C> {
+        self.scan()
+    }
+    pub fn scan(&self) -> ScanIterator<'a, D, C> {
+        ScanIterator {
+            stack: self.new_stack(),
+            tr: *self,
+        }
+    }
+    pub fn search(&self, rect: Rect<D, C>) -> SearchIterator<'a, D, C> {
+        SearchIterator {
+            stack: self.new_stack(),
+            tr: *self,
+            rect,
+        }
+    }
+    pub fn nearby<F>(&self, dist: F) -> NearbyIterator<'a, D, C, F>
+    where
+        F: FnMut(Rect<D, C>, Option<&'a [u8]>) -> C,
+    {
+        let mut iter = NearbyIterator {
+            queue: Queue::new(),
+            tr: *self,
+            dist,
+        };
+        if !self.is_empty() {
+            iter.push(self.node(0), 0);
+        }
+        iter
+    }
+    fn new_stack(&self) -> Vec<std::ops::Range<usize>> {
+        let mut stack = Vec::with_capacity(self.height + 1);
+        if !self.is_empty() {
+            stack.push(self.node(0));
+        }
+        stack
+    }
+}
+
+pub(crate) fn decode_rect<const D: usize, C>(buf: &[u8]) -> Rect<D, C>
+where
+    C: Codec + Copy + Default,
+{
+    let mut rect = Rect {
+        min: [C::default(); D],
+        max: [C::default(); D],
+    };
+    let mut chunks = buf.chunks_exact(C::SIZE);
+    for coord in rect.min.iter_mut().chain(rect.max.iter_mut()) {
+        *coord = C::decode(chunks.next().unwrap());
+    }
+    rect
+}
+
+// scan iterator -- the stack holds the remaining entries of each level, and
+// the entries at the bottom of the stack are items.
+
+/// Iterates over every item.
+pub struct ScanIterator<'a, const D: usize, C>
+where
+    C: PartialOrd + Copy + Default,
+{
+    tr: MappedRTree<'a, D, C>,
+    stack: Vec<std::ops::Range<usize>>,
+}
+
+impl<'a, const D: usize, C> Iterator for ScanIterator<'a, D, C>
+where
+    C: PartialOrd + Copy + Codec + Default,
+{
+    type Item = IterItem<'a, D, C, [u8]>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let height = self.tr.height;
+        while let Some(stack) = self.stack.last_mut() {
+            let entry = match stack.next() {
+                Some(entry) => entry,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            if self.stack.len() > height {
+                return Some(IterItem {
+                    rect: self.tr.entry_rect(entry),
+                    data: self.tr.item(self.tr.entry_ref(entry)),
+                    dist: Default::default(),
+                });
+            }
+            self.stack.push(self.tr.node(self.tr.entry_ref(entry)));
+        }
+        None
+    }
+}
+
+// search iterator -- much like the scan iterator but with a intersects guard.
+
+/// Iterates over the items that intersect a rect.
+pub struct SearchIterator<'a, const D: usize, C>
+where
+    C: PartialOrd + Copy + Default,
+{
+    tr: MappedRTree<'a, D, C>,
+    stack: Vec<std::ops::Range<usize>>,
+    rect: Rect<D, C>,
+}
+
+impl<'a, const D: usize, C> Iterator for SearchIterator<'a, D, C>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Codec + Default,
+{
+    type Item = IterItem<'a, D, C, [u8]>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let height = self.tr.height;
+        while let Some(stack) = self.stack.last_mut() {
+            let tr = &self.tr;
+            let rect = &self.rect;
+            let (entry, erect) = match stack
+                .map(|entry| (entry, tr.entry_rect(entry)))
+                .find(|(_, erect)| erect.intersects(rect))
+            {
+                Some(found) => found,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            if self.stack.len() > height {
+                return Some(IterItem {
+                    rect: erect,
+                    data: self.tr.item(self.tr.entry_ref(entry)),
+                    dist: Default::default(),
+                });
+            }
+            self.stack.push(self.tr.node(self.tr.entry_ref(entry)));
+        }
+        None
+    }
+}
+
+// nearby iterator
+
+struct NearbyItem<C> {
+    dist: C,
+    entry: usize,
+    depth: usize,
+}
+
+impl<C: PartialOrd> PartialEq for NearbyItem<C> {
+    fn eq(&self, other: &NearbyItem<C>) -> bool {
+        self.dist.eq(&other.dist)
+    }
+}
+
+impl<C: PartialOrd> PartialOrd for NearbyItem<C> {
+    fn partial_cmp(&self, other: &NearbyItem<C>) -> Option<Ordering> {
+        self.dist.partial_cmp(&other.dist)
+    }
+}
+
+/// Iterates over the items in order of distance.
+pub struct NearbyIterator<'a, const D: usize, C, F>
+where
+    C: PartialOrd + Copy + Default,
+{
+    tr: MappedRTree<'a, D, C>,
+    queue: Queue<NearbyItem<C>>,
+    dist: F,
+}
+
+impl<'a, const D: usize, C, F> NearbyIterator<'a, D, C, F>
+where
+    C: PartialOrd + Copy + Codec + Default,
+    F: FnMut(Rect<D, C>, Option<&'a [u8]>) -> C,
+{
+    fn push(&mut self, entries: std::ops::Range<usize>, depth: usize) {
+        for entry in entries {
+            let data = if depth == self.tr.height {
+                Some(self.tr.item(self.tr.entry_ref(entry)))
+            } else {
+                None
+            };
+            self.queue.push(NearbyItem {
+                dist: (self.dist)(self.tr.entry_rect(entry), data),
+                entry,
+                depth,
+            });
+        }
+    }
+}
+
+impl<'a, const D: usize, C, F> Iterator for NearbyIterator<'a, D, C, F>
+where
+    C: PartialOrd + Copy + Codec + Default,
+    F: FnMut(Rect<D, C>, Option<&'a [u8]>) -> C,
+{
+    type Item = IterItem<'a, D, C, [u8]>;
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(item) = self.queue.pop() {
+            let index = self.tr.entry_ref(item.entry);
+            if item.depth == self.tr.height {
+                return Some(IterItem {
+                    rect: self.tr.entry_rect(item.entry),
+                    data: self.tr.item(index),
+                    dist: item.dist,
+                });
+            }
+            self.push(self.tr.node(index), item.depth + 1);
+        }
+        None
+    }
+}
@@ -0,0 +1,906 @@
+//! A disk backed tree, for data that does not fit in memory.
+//!
+//! The nodes of a [`PagedRTree`] are stored in fixed size pages of a
+//! [`PageStore`], and the pages that are in use are kept in an LRU buffer
+//! pool. Changes are written to the store when a page is evicted from the
+//! pool, and all at once with [`flush`](PagedRTree::flush).
+//!
+//! Page 0 holds the header of the tree. Every other page is a node or on the
+//! list of free pages.
+
+use super::mapped::{decode_rect, encode_rect, invalid, read_u32, read_u64, rect_size};
+use super::{sort_hilbert, Bounded, Codec, EdgeSnap, InsertStrategy, Options, Rect};
+use pqueue::Queue;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::{Add, Mul, Sub};
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"RTRP";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 72;
+const CACHE_PAGES: usize = 256;
+
+// node pages start with the kind and the number of entries
+const NODE_HEADER_SIZE: usize = 8;
+const FREE: u8 = 0;
+const BRANCH: u8 = 1;
+const LEAF: u8 = 2;
+
+/// Fixed size pages, which are addressed by their number.
+pub trait PageStore {
+    /// The size of every page in bytes.
+    fn page_size(&self) -> usize;
+    /// Reads a page that was written before.
+    fn read_page(&mut self, id: u64, buf: &mut [u8]) -> io::Result<()>;
+    /// Writes a page. Writing past the last page grows the store.
+    fn write_page(&mut self, id: u64, buf: &[u8]) -> io::Result<()>;
+    /// Makes every write durable.
+    fn sync(&mut self) -> io::Result<()>;
+}
+
+impl<P: PageStore + ?Sized> PageStore for Box<P> {
+    fn page_size(&self) -> usize {
+        (**self).page_size()
+    }
+    fn read_page(&mut self, id: u64, buf: &mut [u8]) -> io::Result<()> {
+        (**self).read_page(id, buf)
+    }
+    fn write_page(&mut self, id: u64, buf: &[u8]) -> io::Result<()> {
+        (**self).write_page(id, buf)
+    }
+    fn sync(&mut self) -> io::Result<()> {
+        (**self).sync()
+    }
+}
+
+/// Pages in a file.
+pub struct FilePageStore {
+    file: File,
+    page_size: usize,
+}
+
+impl FilePageStore {
+    /// Opens the file, creating it when it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P, page_size: usize) -> io::Result<FilePageStore> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        Ok(FilePageStore { file, page_size })
+    }
+}
+
+impl PageStore for FilePageStore {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+    fn read_page(&mut self, id: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.file
+            .seek(SeekFrom::Start(id * self.page_size as u64))?;
+        self.file.read_exact(buf)
+    }
+    fn write_page(&mut self, id: u64, buf: &[u8]) -> io::Result<()> {
+        self.file
+            .seek(SeekFrom::Start(id * self.page_size as u64))?;
+        self.file.write_all(buf)
+    }
+    fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+}
+
+/// Pages in memory. Mostly useful for testing.
+#[derive(Clone, Debug)]
+pub struct MemoryPageStore {
+    pages: Vec<Vec<u8>>,
+    page_size: usize,
+}
+
+impl MemoryPageStore {
+    pub fn new(page_size: usize) -> MemoryPageStore {
+        MemoryPageStore {
+            pages: Vec::new(),
+            page_size,
+        }
+    }
+}
+
+impl PageStore for MemoryPageStore {
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+    fn read_page(&mut self, id: u64, buf: &mut [u8]) -> io::Result<()> {
+        match self.pages.get(id as usize) {
+            Some(page) if !page.is_empty() => {
+                buf.copy_from_slice(page);
+                Ok(())
+            }
+            _ => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "missing page")),
+        }
+    }
+    fn write_page(&mut self, id: u64, buf: &[u8]) -> io::Result<()> {
+        let id = id as usize;
+        if id >= self.pages.len() {
+            self.pages.resize(id + 1, Vec::new());
+        }
+        self.pages[id] = buf.to_vec();
+        Ok(())
+    }
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// buffer pool -- the least recently used page is evicted first, and written
+// to the store when it changed.
+
+struct Frame {
+    page: Vec<u8>,
+    dirty: bool,
+    tick: u64,
+}
+
+struct BufferPool<P> {
+    store: P,
+    capacity: usize,
+    frames: HashMap<u64, Frame>,
+    lru: BTreeMap<u64, u64>,
+    tick: u64,
+}
+
+impl<P: PageStore> BufferPool<P> {
+    fn new(store: P) -> BufferPool<P> {
+        BufferPool {
+            store,
+            capacity: CACHE_PAGES,
+            frames: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+    fn touch(&mut self, id: u64) -> &mut Frame {
+        let frame = self.frames.get_mut(&id).unwrap();
+        self.lru.remove(&frame.tick);
+        self.tick += 1;
+        frame.tick = self.tick;
+        self.lru.insert(self.tick, id);
+        frame
+    }
+    fn get(&mut self, id: u64) -> io::Result<&[u8]> {
+        if !self.frames.contains_key(&id) {
+            let mut page = vec![0; self.store.page_size()];
+            self.store.read_page(id, &mut page)?;
+            self.evict(1)?;
+            self.frames.insert(
+                id,
+                Frame {
+                    page,
+                    dirty: false,
+                    tick: 0,
+                },
+            );
+        }
+        Ok(&self.touch(id).page)
+    }
+    fn put(&mut self, id: u64, page: Vec<u8>) -> io::Result<()> {
+        if !self.frames.contains_key(&id) {
+            self.evict(1)?;
+            self.frames.insert(
+                id,
+                Frame {
+                    page,
+                    dirty: true,
+                    tick: 0,
+                },
+            );
+        } else {
+            let frame = self.frames.get_mut(&id).unwrap();
+            frame.page = page;
+            frame.dirty = true;
+        }
+        self.touch(id);
+        Ok(())
+    }
+    // Makes room for the number of new pages.
+    fn evict(&mut self, room: usize) -> io::Result<()> {
+        while !self.frames.is_empty() && self.frames.len() + room > self.capacity {
+            let (_, id) = self.lru.pop_first().unwrap();
+            let frame = self.frames.remove(&id).unwrap();
+            if frame.dirty {
+                self.store.write_page(id, &frame.page)?;
+            }
+        }
+        Ok(())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        let mut dirty: Vec<_> = self.frames.iter_mut().filter(|(_, f)| f.dirty).collect();
+        dirty.sort_by_key(|(id, _)| **id);
+        for (id, frame) in dirty {
+            self.store.write_page(*id, &frame.page)?;
+            frame.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+// nodes -- a page holds the entries of a single node, which are either
+// child pages or items.
+
+enum Child<T> {
+    Page(u64),
+    Item(T),
+}
+
+struct Entry<const D: usize, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    rect: Rect<D, C>,
+    child: Child<T>,
+}
+
+impl<const D: usize, C, T> Entry<D, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    fn page(&self) -> u64 {
+        match self.child {
+            Child::Page(id) => id,
+            _ => panic!("not a page"),
+        }
+    }
+    fn item(&self) -> &T {
+        match &self.child {
+            Child::Item(data) => data,
+            _ => panic!("not an item"),
+        }
+    }
+}
+
+impl<const D: usize, C, T> Bounded<D, C> for Entry<D, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    fn rect(&self) -> &Rect<D, C> {
+        &self.rect
+    }
+}
+
+// The union of the entries, or an empty rect when there are none.
+fn bounds<const D: usize, C, T>(entries: &[Entry<D, C, T>]) -> Rect<D, C>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    let mut rect = Rect {
+        min: [C::default(); D],
+        max: [C::default(); D],
+    };
+    if let Some(first) = entries.first() {
+        rect = first.rect;
+        for entry in &entries[1..] {
+            rect.expand(&entry.rect);
+        }
+    }
+    rect
+}
+
+// What a remove returns for every page on the path to the item.
+struct Removed<const D: usize, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    item: (Rect<D, C>, T),
+    rect: Rect<D, C>,
+    len: usize,
+}
+
+/// An item that was read from the pages.
+#[derive(Debug)]
+pub struct PagedItem<const D: usize, C: Default, T> {
+    pub rect: Rect<D, C>,
+    pub data: T,
+    pub dist: C,
+}
+
+/// An RTree that is stored in the pages of a [`PageStore`]. The items are
+/// encoded with their [`Codec`], and the node fanout is limited to what
+/// fits in a page.
+///
+/// Changes are not durable until [`flush`](PagedRTree::flush), which is not
+/// done when the tree is dropped. The pages are updated in place, so a crash
+/// during a flush, or after a page was evicted from the pool, can leave the
+/// store inconsistent.
+///
+/// Strategies are used to choose subtrees and to split nodes, but entries
+/// are never reinserted by a strategy.
+pub struct PagedRTree<const D: usize, C, T, P, S = EdgeSnap>
+where
+    C: PartialOrd + Copy + Default,
+{
+    pool: BufferPool<P>,
+    opts: Options,
+    strategy: S,
+    root: Option<u64>,
+    height: usize,
+    length: usize,
+    // the first free page, or zero when there are none
+    free: u64,
+    // the number of pages, including the header and the free pages
+    pages: u64,
+    _data: std::marker::PhantomData<(Rect<D, C>, T)>,
+}
+
+impl<const D: usize, C, T, P, S> PagedRTree<D, C, T, P, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Codec + Default,
+    T: Codec,
+    P: PageStore,
+    S: InsertStrategy,
+{
+    /// The largest fanout that fits in a page of the size.
+    pub fn max_items(page_size: usize) -> usize {
+        page_size.saturating_sub(NODE_HEADER_SIZE) / Self::entry_size()
+    }
+    fn entry_size() -> usize {
+        rect_size::<D, C>() + T::SIZE.max(8)
+    }
+    /// Creates an empty tree in the store, overwriting anything that was in
+    /// it. Fails when a node with the fanout does not fit in a page, see
+    /// [`max_items`](PagedRTree::max_items).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options are invalid, see [`RTree::with_options`](super::RTree::with_options).
+    pub fn create(store: P, opts: Options) -> io::Result<PagedRTree<D, C, T, P, S>>
+    where
+        S: Default,
+    {
+        opts.validate();
+        if opts.max_items > Self::max_items(store.page_size()) || store.page_size() < HEADER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "page size too small for the node fanout",
+            ));
+        }
+        let mut tr = PagedRTree {
+            pool: BufferPool::new(store),
+            opts,
+            strategy: S::default(),
+            root: None,
+            height: 0,
+            length: 0,
+            free: 0,
+            pages: 1,
+            _data: std::marker::PhantomData,
+        };
+        tr.flush()?;
+        Ok(tr)
+    }
+    /// Opens a tree that was created in the store. Fails when the store
+    /// does not hold a tree of the same dimensions, coordinates and items.
+    pub fn open(mut store: P) -> io::Result<PagedRTree<D, C, T, P, S>>
+    where
+        S: Default,
+    {
+        let page_size = store.page_size();
+        if page_size < HEADER_SIZE {
+            return Err(invalid("not a paged rtree"));
+        }
+        let mut page = vec![0; page_size];
+        store.read_page(0, &mut page)?;
+        if &page[..4] != MAGIC {
+            return Err(invalid("not a paged rtree"));
+        }
+        if read_u32(&page, 4) != VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let sizes = [page_size, D, C::SIZE, T::SIZE];
+        if (0..4).any(|i| read_u32(&page, 8 + i * 4) as usize != sizes[i]) {
+            return Err(invalid("page size, dimensions or data size do not match"));
+        }
+        let opts = Options {
+            max_items: read_u32(&page, 24) as usize,
+            min_items: read_u32(&page, 28) as usize,
+        };
+        if opts.check().is_err() || opts.max_items > Self::max_items(page_size) {
+            return Err(invalid("invalid node fanout"));
+        }
+        let root = read_u64(&page, 48);
+        Ok(PagedRTree {
+            pool: BufferPool::new(store),
+            opts,
+            strategy: S::default(),
+            root: if root == 0 { None } else { Some(root) },
+            height: read_u64(&page, 32) as usize,
+            length: read_u64(&page, 40) as usize,
+            free: read_u64(&page, 56),
+            pages: read_u64(&page, 64),
+            _data: std::marker::PhantomData,
+        })
+    }
+    /// Writes every changed page and the header to the store, and syncs it.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.pool.flush()?;
+        self.pool.store.sync()?;
+        let mut page = Vec::with_capacity(self.page_size());
+        page.extend_from_slice(MAGIC);
+        let sizes = [VERSION as usize, self.page_size(), D, C::SIZE, T::SIZE];
+        for n in sizes
+            .into_iter()
+            .chain([self.opts.max_items, self.opts.min_items])
+        {
+            page.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        let root = self.root.unwrap_or(0);
+        for n in [
+            self.height as u64,
+            self.length as u64,
+            root,
+            self.free,
+            self.pages,
+        ] {
+            page.extend_from_slice(&n.to_le_bytes());
+        }
+        page.resize(self.page_size(), 0);
+        self.pool.store.write_page(0, &page)?;
+        self.pool.store.sync()
+    }
+    /// Flushes the tree and returns the store.
+    pub fn into_store(mut self) -> io::Result<P> {
+        self.flush()?;
+        Ok(self.pool.store)
+    }
+    /// Sets the number of pages that the buffer pool holds in memory.
+    pub fn set_cache_size(&mut self, pages: usize) -> io::Result<()> {
+        self.pool.capacity = pages.max(1);
+        self.pool.evict(0)
+    }
+    /// The number of pages that the tree uses, including the header and
+    /// the free pages.
+    pub fn page_count(&self) -> u64 {
+        self.pages
+    }
+    pub fn options(&self) -> &Options {
+        &self.opts
+    }
+    pub fn len(&self) -> usize {
+        self.length
+    }
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+    pub fn rect(&mut self) -> io::Result<Option<Rect<D, C>>> {
+        match self.root {
+            Some(root) => Ok(Some(bounds(&self.read_node(root)?))),
+            None => Ok(None),
+        }
+    }
+    fn page_size(&self) -> usize {
+        self.pool.store.page_size()
+    }
+    fn read_node(&mut self, id: u64) -> io::Result<Vec<Entry<D, C, T>>> {
+        let page = self.pool.get(id)?;
+        let leaf = match page[0] {
+            BRANCH => false,
+            LEAF => true,
+            _ => return Err(invalid("not a node page")),
+        };
+        let count = read_u32(page, 4) as usize;
+        let rsize = rect_size::<D, C>();
+        let esize = Self::entry_size();
+        if NODE_HEADER_SIZE + count * esize > page.len() {
+            return Err(invalid("node page overflow"));
+        }
+        let mut entries = Vec::with_capacity(count + 1);
+        for i in 0..count {
+            let entry = &page[NODE_HEADER_SIZE + i * esize..][..esize];
+            let child = if leaf {
+                Child::Item(T::decode(&entry[rsize..rsize + T::SIZE]))
+            } else {
+                Child::Page(read_u64(entry, rsize))
+            };
+            entries.push(Entry {
+                rect: decode_rect(entry),
+                child,
+            });
+        }
+        Ok(entries)
+    }
+    fn write_node(&mut self, id: u64, entries: &[Entry<D, C, T>]) -> io::Result<()> {
+        let mut page = Vec::with_capacity(self.page_size());
+        let leaf = matches!(
+            entries.first(),
+            Some(Entry {
+                child: Child::Item(_),
+                ..
+            })
+        );
+        page.extend_from_slice(&[if leaf { LEAF } else { BRANCH }, 0, 0, 0]);
+        page.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for entry in entries {
+            let start = page.len();
+            encode_rect(&entry.rect, &mut page);
+            let end = start + Self::entry_size();
+            page.resize(end, 0);
+            match &entry.child {
+                Child::Page(id) => id.encode(&mut page[end - 8..]),
+                Child::Item(data) => {
+                    let start = start + rect_size::<D, C>();
+                    data.encode(&mut page[start..start + T::SIZE]);
+                }
+            }
+        }
+        page.resize(self.page_size(), 0);
+        self.pool.put(id, page)
+    }
+    fn alloc(&mut self) -> io::Result<u64> {
+        if self.free == 0 {
+            self.pages += 1;
+            return Ok(self.pages - 1);
+        }
+        let id = self.free;
+        let page = self.pool.get(id)?;
+        if page[0] != FREE {
+            return Err(invalid("not a free page"));
+        }
+        self.free = read_u64(page, 8);
+        Ok(id)
+    }
+    fn free(&mut self, id: u64) -> io::Result<()> {
+        let mut page = vec![0; self.page_size()];
+        page[0] = FREE;
+        self.free.encode(&mut page[8..16]);
+        self.free = id;
+        self.pool.put(id, page)
+    }
+    pub fn insert(&mut self, rect: Rect<D, C>, data: T) -> io::Result<()> {
+        let entry = Entry {
+            rect,
+            child: Child::Item(data),
+        };
+        match self.root {
+            None => {
+                let root = self.alloc()?;
+                self.write_node(root, &[entry])?;
+                self.root = Some(root);
+                self.height = 0;
+            }
+            Some(root) => {
+                let mut left = Entry {
+                    rect,
+                    child: Child::Page(root),
+                };
+                if let Some(right) = self.insert_page(&mut left, entry, self.height)? {
+                    let root = self.alloc()?;
+                    self.write_node(root, &[left, right])?;
+                    self.root = Some(root);
+                    self.height += 1;
+                }
+            }
+        }
+        self.length += 1;
+        Ok(())
+    }
+    // Inserts the entry into the subtree of the page that the node entry
+    // points to, and updates its rect. Returns the page that was split off
+    // of it, if any.
+    fn insert_page(
+        &mut self,
+        node: &mut Entry<D, C, T>,
+        entry: Entry<D, C, T>,
+        height: usize,
+    ) -> io::Result<Option<Entry<D, C, T>>> {
+        let id = node.page();
+        let mut entries = self.read_node(id)?;
+        if height == 0 {
+            entries.push(entry);
+        } else {
+            let index = if D == 0 {
+                0
+            } else {
+                self.strategy
+                    .choose_subtree(&entries, &entry.rect, height == 1)
+            };
+            let right = self.insert_page(&mut entries[index], entry, height - 1)?;
+            entries.extend(right);
+        }
+        let mut split = None;
+        if entries.len() == self.opts.max_items {
+            let mut right = Vec::new();
+            let rect = bounds(&entries);
+            self.strategy
+                .split(&rect, &mut entries, &mut right, &self.opts);
+            debug_assert!(
+                entries.len() >= self.opts.min_items && right.len() >= self.opts.min_items,
+                "the strategy split off a side with too few children"
+            );
+            let page = self.alloc()?;
+            self.write_node(page, &right)?;
+            split = Some(Entry {
+                rect: bounds(&right),
+                child: Child::Page(page),
+            });
+        }
+        self.write_node(id, &entries)?;
+        node.rect = bounds(&entries);
+        Ok(split)
+    }
+    /// Removes the first item within the rect that the predicate returns
+    /// true for.
+    pub fn remove_by<F>(
+        &mut self,
+        rect: Rect<D, C>,
+        mut pred: F,
+    ) -> io::Result<Option<(Rect<D, C>, T)>>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let Some(root) = self.root else {
+            return Ok(None);
+        };
+        let mut reinsert = Vec::new();
+        let removed = self.remove_page(root, &rect, &mut pred, self.height, &mut reinsert)?;
+        let Some(removed) = removed else {
+            return Ok(None);
+        };
+        self.length -= reinsert.len() + 1;
+        if removed.len == 0 {
+            self.free(root)?;
+            self.root = None;
+            self.height = 0;
+        } else if self.height > 0 && removed.len == 1 {
+            let entries = self.read_node(root)?;
+            self.free(root)?;
+            self.root = Some(entries[0].page());
+            self.height -= 1;
+        }
+        // reinsert in Hilbert order to keep the inserts local
+        sort_hilbert(&mut reinsert, |item| &item.0);
+        for (rect, data) in reinsert {
+            self.insert(rect, data)?;
+        }
+        Ok(Some(removed.item))
+    }
+    // Removes the item from the subtree of the page. The children that
+    // underflow are freed, and their items are moved into reinsert.
+    fn remove_page<F>(
+        &mut self,
+        id: u64,
+        rect: &Rect<D, C>,
+        pred: &mut F,
+        height: usize,
+        reinsert: &mut Vec<(Rect<D, C>, T)>,
+    ) -> io::Result<Option<Removed<D, C, T>>>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut entries = self.read_node(id)?;
+        let mut item = None;
+        if height == 0 {
+            if let Some(i) = entries.iter().position(|entry| pred(entry.item())) {
+                let entry = entries.swap_remove(i);
+                if let Child::Item(data) = entry.child {
+                    item = Some((entry.rect, data));
+                }
+            }
+        } else {
+            for i in 0..entries.len() {
+                if !entries[i].rect.intersects(rect) {
+                    continue;
+                }
+                let removed =
+                    self.remove_page(entries[i].page(), rect, pred, height - 1, reinsert)?;
+                let Some(removed) = removed else {
+                    continue;
+                };
+                if removed.len < self.opts.min_items {
+                    let child = entries.swap_remove(i).page();
+                    self.take_items(child, reinsert)?;
+                } else {
+                    entries[i].rect = removed.rect;
+                }
+                item = Some(removed.item);
+                break;
+            }
+        }
+        let Some(item) = item else {
+            return Ok(None);
+        };
+        self.write_node(id, &entries)?;
+        Ok(Some(Removed {
+            item,
+            rect: bounds(&entries),
+            len: entries.len(),
+        }))
+    }
+    // Frees every page of the subtree, and moves its items into out.
+    fn take_items(&mut self, id: u64, out: &mut Vec<(Rect<D, C>, T)>) -> io::Result<()> {
+        let entries = self.read_node(id)?;
+        self.free(id)?;
+        for entry in entries {
+            match entry.child {
+                Child::Item(data) => out.push((entry.rect, data)),
+                Child::Page(id) => self.take_items(id, out)?,
+            }
+        }
+        Ok(())
+    }
+    pub fn search(&mut self, rect: Rect<D, C>) -> SearchIterator<'_, D, C, T, P, S> {
+        SearchIterator {
+            root: self.root,
+            tr: self,
+            stack: Vec::new(),
+            rect,
+        }
+    }
+    pub fn nearby<F>(&mut self, dist: F) -> NearbyIterator<'_, D, C, T, P, S, F>
+    where
+        F: FnMut(Rect<D, C>, Option<&T>) -> C,
+    {
+        NearbyIterator {
+            root: self.root,
+            tr: self,
+            queue: Queue::new(),
+            dist,
+        }
+    }
+}
+
+impl<const D: usize, C, T, P, S> PagedRTree<D, C, T, P, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Codec + Default,
+    T: Codec + PartialEq,
+    P: PageStore,
+    S: InsertStrategy,
+{
+    pub fn remove(&mut self, rect: Rect<D, C>, data: &T) -> io::Result<Option<(Rect<D, C>, T)>> {
+        self.remove_by(rect, |item| item == data)
+    }
+}
+
+// search iterator -- the stack holds the remaining entries of each level,
+// which are read when the iterator gets to them. Stops after an error.
+
+/// Iterates over the items that intersect a rect.
+pub struct SearchIterator<'a, const D: usize, C, T, P, S>
+where
+    C: PartialOrd + Copy + Default,
+{
+    tr: &'a mut PagedRTree<D, C, T, P, S>,
+    root: Option<u64>,
+    stack: Vec<std::vec::IntoIter<Entry<D, C, T>>>,
+    rect: Rect<D, C>,
+}
+
+impl<'a, const D: usize, C, T, P, S> Iterator for SearchIterator<'a, D, C, T, P, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Codec + Default,
+    T: Codec,
+    P: PageStore,
+    S: InsertStrategy,
+{
+    type Item = io::Result<PagedItem<D, C, T>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut page = self.root.take();
+        loop {
+            if let Some(id) = page.take() {
+                match self.tr.read_node(id) {
+                    Ok(entries) => self.stack.push(entries.into_iter()),
+                    Err(err) => {
+                        self.stack.clear();
+                        return Some(Err(err));
+                    }
+                }
+            }
+            let stack = self.stack.last_mut()?;
+            let entry = match stack.find(|entry| entry.rect.intersects(&self.rect)) {
+                Some(entry) => entry,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            match entry.child {
+                Child::Item(data) => {
+                    return Some(Ok(PagedItem {
+                        rect: entry.rect,
+                        data,
+                        dist: Default::default(),
+                    }));
+                }
+                Child::Page(id) => page = Some(id),
+            }
+        }
+    }
+}
+
+// nearby iterator
+
+struct NearbyItem<const D: usize, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    dist: C,
+    entry: Entry<D, C, T>,
+}
+
+impl<const D: usize, C, T> PartialEq for NearbyItem<D, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    fn eq(&self, other: &NearbyItem<D, C, T>) -> bool {
+        self.dist.eq(&other.dist)
+    }
+}
+
+impl<const D: usize, C, T> PartialOrd for NearbyItem<D, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    fn partial_cmp(&self, other: &NearbyItem<D, C, T>) -> Option<Ordering> {
+        self.dist.partial_cmp(&other.dist)
+    }
+}
+
+/// Iterates over the items in order of distance. Stops after an error.
+pub struct NearbyIterator<'a, const D: usize, C, T, P, S, F>
+where
+    C: PartialOrd + Copy + Default,
+{
+    tr: &'a mut PagedRTree<D, C, T, P, S>,
+    root: Option<u64>,
+    queue: Queue<NearbyItem<D, C, T>>,
+    dist: F,
+}
+
+impl<'a, const D: usize, C, T, P, S, F> Iterator for NearbyIterator<'a, D, C, T, P, S, F>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Codec + Default,
+    T: Codec,
+    P: PageStore,
+    S: InsertStrategy,
+    F: FnMut(Rect<D, C>, Option<&T>) -> C,
+{
+    type Item = io::Result<PagedItem<D, C, T>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut page = self.root.take();
+        loop {
+            if let Some(id) = page.take() {
+                match self.tr.read_node(id) {
+                    Ok(entries) => {
+                        for entry in entries {
+                            let data = match &entry.child {
+                                Child::Item(data) => Some(data),
+                                Child::Page(_) => None,
+                            };
+                            self.queue.push(NearbyItem {
+                                dist: (self.dist)(entry.rect, data),
+                                entry,
+                            });
+                        }
+                    }
+                    Err(err) => {
+                        self.queue = Queue::new();
+                        return Some(Err(err));
+                    }
+                }
+            }
+            let item = self.queue.pop()?;
+            match item.entry.child {
+                Child::Item(data) => {
+                    return Some(Ok(PagedItem {
+                        rect: item.entry.rect,
+                        data,
+                        dist: item.dist,
+                    }));
+                }
+                Child::Page(id) => page = Some(id),
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! A persistent variant of the RTree. The children of branch nodes are held
+//! behind an Arc and shared between copies of the tree. Inserts and removes
+//! copy only the nodes on the path that they change, which leaves any other
+//! copy untouched, so that taking a snapshot is O(1).
+
+use super::{
+    EdgeSnap, InsertStrategy, NearbyIterator, Options, RTree, Rect, ScanIterator, SearchIterator,
+    SearchWithIterator, Shared, SpatialPredicate,
+};
+use std::ops::{Add, Mul, Sub};
+
+/// An RTree with structural sharing. Cloning the tree, or taking a
+/// [`snapshot`](PersistentRTree::snapshot), is O(1) and the copies share
+/// all of their nodes. A change to one copy only copies the nodes on the
+/// path that it touches, so readers can keep searching a snapshot while a
+/// writer keeps changing the tree.
+///
+/// The tree runs the same code as [`RTree`], which it wraps with
+/// [`Shared`] storage. Changes require that the data is `Clone`, which is
+/// used for the leaves that are still shared with another copy. The
+/// searches that are not forwarded here are on [`tree`](PersistentRTree::tree).
+///
+/// The tree and its iterators are `Send` and `Sync` when `C` and `T` are
+/// both `Send` and `Sync`, as the nodes are shared through an `Arc`. A
+/// snapshot may be sent to other threads while the original keeps
+/// changing, see also [`ConcurrentRTree`](super::ConcurrentRTree).
+pub struct PersistentRTree<const D: usize, C, T, S = EdgeSnap>
+where
+    C: PartialOrd + Copy + Default,
+{
+    tree: RTree<D, C, T, S, Shared>,
+}
+
+impl<const D: usize, C, T, S: Clone> Clone for PersistentRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    fn clone(&self) -> PersistentRTree<D, C, T, S> {
+        PersistentRTree {
+            tree: self.tree.share(),
+        }
+    }
+}
+
+impl<const D: usize, C, T, S: InsertStrategy + Default> Default for PersistentRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    fn default() -> PersistentRTree<D, C, T, S> {
+        PersistentRTree::with_strategy(Options::default(), S::default())
+    }
+}
+
+impl<const D: usize, C, T, S> From<RTree<D, C, T, S>> for PersistentRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Default,
+{
+    // Useful for bulk loading
+    fn from(tree: RTree<D, C, T, S>) -> PersistentRTree<D, C, T, S> {
+        PersistentRTree {
+            tree: tree.into_shared(),
+        }
+    }
+}
+
+impl<const D: usize, C, T> PersistentRTree<D, C, T>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    pub fn new() -> PersistentRTree<D, C, T> {
+        PersistentRTree::with_options(Options::default())
+    }
+    /// Creates an empty tree with the provided node fanout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options are invalid, see [`RTree::with_options`].
+    pub fn with_options(opts: Options) -> PersistentRTree<D, C, T> {
+        PersistentRTree::with_strategy(opts, EdgeSnap)
+    }
+}
+
+impl<const D: usize, C, T, S: InsertStrategy> PersistentRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    /// Creates an empty tree with the provided node fanout and strategy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options are invalid, see [`RTree::with_options`].
+    pub fn with_strategy(opts: Options, strategy: S) -> PersistentRTree<D, C, T, S> {
+        PersistentRTree::from(RTree::with_strategy(opts, strategy))
+    }
+    /// Returns a copy of the tree in O(1). Later changes to either tree are
+    /// not seen by the other.
+    pub fn snapshot(&self) -> PersistentRTree<D, C, T, S>
+    where
+        S: Clone,
+    {
+        self.clone()
+    }
+    /// Returns the tree for reading, such as for the searches that are not
+    /// forwarded here.
+    pub fn tree(&self) -> &RTree<D, C, T, S, Shared> {
+        &self.tree
+    }
+    pub fn options(&self) -> Options {
+        self.tree.options()
+    }
+    pub fn strategy(&self) -> &S {
+        self.tree.strategy()
+    }
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    pub fn rect(&self) -> Option<Rect<D, C>> {
+        self.tree.rect()
+    }
+}
+
+impl<const D: usize, C, T: Clone, S: InsertStrategy> PersistentRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    pub fn insert(&mut self, rect: Rect<D, C>, data: T) {
+        self.tree.insert_item(rect, data);
+    }
+    /// Removes the first item within the rect that the predicate returns
+    /// true for. Nothing is copied when there is no such item.
+    pub fn remove_by<F>(&mut self, rect: Rect<D, C>, pred: F) -> Option<(Rect<D, C>, T)>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.tree.remove_item(rect, pred)
+    }
+}
+
+impl<const D: usize, C, T: Clone + PartialEq, S: InsertStrategy> PersistentRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    pub fn remove(&mut self, rect: Rect<D, C>, data: &T) -> Option<(Rect<D, C>, T)> {
+        self.remove_by(rect, |item| item == data)
+    }
+}
+
+impl<const D: usize, C, T, S> PersistentRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
+{
+    pub fn iter(&self) -> ScanIterator<'_, D, C, T, Shared> {
+        self.tree.iter()
+    }
+
+    pub fn scan(&self) -> ScanIterator<'_, D, C, T, Shared> {
+        self.tree.scan()
+    }
+
+    pub fn search(&self, rect: Rect<D, C>) -> SearchIterator<'_, D, C, T, Shared> {
+        self.tree.search(rect)
+    }
+
+    /// Returns the items that match the predicate, see [`RTree::search_with`].
+    pub fn search_with<P>(&self, pred: P) -> SearchWithIterator<'_, D, C, T, P, Shared>
+    where
+        P: SpatialPredicate<D, C, T>,
+    {
+        self.tree.search_with(pred)
+    }
+
+    pub fn nearby<'a, F>(&'a self, dist: F) -> NearbyIterator<'a, D, C, T, F, Shared>
+    where
+        F: FnMut(Rect<D, C>, Option<&'a T>) -> C,
+    {
+        self.tree.nearby(dist)
+    }
+}
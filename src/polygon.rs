@@ -0,0 +1,229 @@
+use super::{Data, Node, Owned, RTree, Rect, Segment, StackNode, Storage};
+use std::ops::{Add, Mul, Sub};
+
+/// An item of a [`search_polygon`](RTree::search_polygon), and whether its
+/// rect is completely inside of the polygon.
+#[derive(Debug)]
+pub struct PolygonItem<'a, C: Default, T> {
+    pub rect: Rect<2, C>,
+    pub data: &'a T,
+    pub inside: bool,
+}
+
+impl<C, T, S, K: Storage> RTree<2, C, T, S, K>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    /// Returns the items that intersect the polygon, edges included. The
+    /// polygon is closed from the last point back to the first, and may be
+    /// concave but should not cross itself. Integers must be signed.
+    ///
+    /// Nodes that are outside of the polygon are skipped, and the items of
+    /// nodes that are completely inside are returned without testing them.
+    pub fn search_polygon<'a>(
+        &'a self,
+        polygon: &'a [[C; 2]],
+    ) -> SearchPolygonIterator<'a, C, T, K> {
+        let mut iter = SearchPolygonIterator {
+            stack: Vec::new(),
+            polygon: Polygon::new(polygon),
+        };
+        if let Some(root) = &self.root {
+            if let Some(inside) = iter.polygon.classify(&root.rect) {
+                let stack = StackNode::new_stack(&self.root, self.height);
+                iter.stack = stack.into_iter().map(|stack| (stack, inside)).collect();
+            }
+        }
+        iter
+    }
+}
+
+// A polygon and its bounds. All of the tests are done without division.
+struct Polygon<'a, C: Default> {
+    points: &'a [[C; 2]],
+    bounds: Option<Rect<2, C>>,
+}
+
+impl<'a, C> Polygon<'a, C>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    fn new(points: &'a [[C; 2]]) -> Polygon<'a, C> {
+        let mut bounds: Option<Rect<2, C>> = None;
+        for point in points {
+            let rect = Rect::new_point(*point);
+            match &mut bounds {
+                Some(bounds) => bounds.expand(&rect),
+                None => bounds = Some(rect),
+            }
+        }
+        Polygon { points, bounds }
+    }
+
+    fn edge(&self, i: usize) -> Segment<2, C> {
+        Segment {
+            a: self.points[i],
+            b: self.points[(i + 1) % self.points.len()],
+        }
+    }
+
+    // Returns none when the rect is outside of the polygon, and otherwise
+    // whether the rect is completely inside.
+    fn classify(&self, rect: &Rect<2, C>) -> Option<bool> {
+        match &self.bounds {
+            Some(bounds) if bounds.intersects(rect) => {}
+            _ => return None,
+        }
+        let mut touches = false;
+        let mut inside = true;
+        for i in 0..self.points.len() {
+            let edge = self.edge(i);
+            if edge.crosses(rect) {
+                touches = true;
+                if crosses_interior(&edge, rect) {
+                    inside = false;
+                    break;
+                }
+            }
+        }
+        if !inside {
+            return Some(false);
+        }
+        // nothing of the boundary goes through the interior of the rect, so
+        // the interior is either all inside or all outside, which its center
+        // tells. The center is tested on a polygon of twice the size.
+        let center = [rect.min[0] + rect.max[0], rect.min[1] + rect.max[1]];
+        if self.contains_point(center, true) {
+            Some(true)
+        } else if touches {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    // Returns true when the point is inside of the polygon or on its
+    // boundary, by counting the edges that a ray to the right crosses.
+    fn contains_point(&self, point: [C; 2], double: bool) -> bool {
+        let n = self.points.len();
+        let mut inside = false;
+        for i in 0..n {
+            let (mut a, mut b) = (self.points[i], self.points[(i + 1) % n]);
+            if double {
+                a = [a[0] + a[0], a[1] + a[1]];
+                b = [b[0] + b[0], b[1] + b[1]];
+            }
+            // the side of the edge that the point is on
+            let l = (b[0] - a[0]) * (point[1] - a[1]);
+            let r = (b[1] - a[1]) * (point[0] - a[0]);
+            if l == r {
+                let edge = Segment { a, b };
+                if edge.crosses(&Rect::new_point(point)) {
+                    return true;
+                }
+            }
+            // an edge counts when it goes from below the point to above it,
+            // or the other way, with the lower end included
+            if (a[1] <= point[1]) != (b[1] <= point[1]) {
+                let left = if b[1] > a[1] { l > r } else { l < r };
+                if left {
+                    inside = !inside;
+                }
+            }
+        }
+        inside
+    }
+}
+
+// Returns true when the segment passes through the inside of the rect, not
+// counting the edges. For a rect that is a line, the inside is the line
+// without its ends, and a point has none. Like Segment::crosses, with the
+// inequalities made strict on the axes where the rect has an extent.
+fn crosses_interior<C>(edge: &Segment<2, C>, rect: &Rect<2, C>) -> bool
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    for i in 0..2 {
+        let (lo, hi) = if edge.a[i] < edge.b[i] {
+            (edge.a[i], edge.b[i])
+        } else {
+            (edge.b[i], edge.a[i])
+        };
+        let outside = if rect.min[i] < rect.max[i] {
+            lo >= rect.max[i] || hi <= rect.min[i]
+        } else {
+            lo > rect.max[i] || hi < rect.min[i]
+        };
+        if outside {
+            return false;
+        }
+    }
+    let di = edge.b[0] - edge.a[0];
+    let dj = edge.b[1] - edge.a[1];
+    let zero = Default::default();
+    if di == zero && dj == zero {
+        return true;
+    }
+    let (mut left, mut right) = (false, false);
+    for x in [rect.min[0], rect.max[0]] {
+        for y in [rect.min[1], rect.max[1]] {
+            let l = di * (y - edge.a[1]);
+            let r = dj * (x - edge.a[0]);
+            left |= l > r;
+            right |= l < r;
+        }
+    }
+    left && right
+}
+
+// search polygon iterator -- the search iterator, where each level of the
+// stack also tells whether its nodes are known to be inside of the polygon.
+
+/// Iterates over the items that intersect a polygon. `Send` and `Sync` when
+/// `C` and `T` are `Sync`.
+pub struct SearchPolygonIterator<'a, C, T, K: Storage = Owned>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Default,
+{
+    stack: Vec<(StackNode<'a, 2, C, T, K>, bool)>,
+    polygon: Polygon<'a, C>,
+}
+
+impl<'a, C, T, K: Storage> Iterator for SearchPolygonIterator<'a, C, T, K>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    type Item = PolygonItem<'a, C, T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        'outer: while let Some((stack, all_inside)) = self.stack.last_mut() {
+            let all_inside = *all_inside;
+            for i in stack.index..stack.nodes.len() {
+                let node: &'a Node<2, C, T, K> = &stack.nodes[i];
+                let inside = if all_inside {
+                    true
+                } else {
+                    match self.polygon.classify(&node.rect) {
+                        Some(inside) => inside,
+                        None => continue,
+                    }
+                };
+                stack.index = i + 1;
+                match &node.data {
+                    Data::Item(data) => {
+                        return Some(PolygonItem {
+                            rect: node.rect,
+                            data,
+                            inside,
+                        });
+                    }
+                    Data::Nodes(nodes) => {
+                        self.stack.push((StackNode { nodes, index: 0 }, inside));
+                        continue 'outer;
+                    }
+                }
+            }
+            self.stack.pop();
+        }
+        None
+    }
+}
@@ -0,0 +1,129 @@
+use super::Rect;
+use std::ops::{Add, Mul, Sub};
+
+/// A spatial query, which decides what [`RTree::search_with`](super::RTree::search_with)
+/// returns and which nodes it visits.
+pub trait SpatialPredicate<const D: usize, C: Default, T> {
+    /// Returns false when no item inside of a node with the rect can match,
+    /// which skips the node.
+    fn node_may_match(&self, rect: &Rect<D, C>) -> bool;
+    /// Returns true when the item matches.
+    fn item_matches(&self, rect: &Rect<D, C>, data: &T) -> bool;
+}
+
+/// Matches the items that intersect the rect, like [`RTree::search`](super::RTree::search).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Intersects<const D: usize, C: Default>(pub Rect<D, C>);
+
+impl<const D: usize, C, T> SpatialPredicate<D, C, T> for Intersects<D, C>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    fn node_may_match(&self, rect: &Rect<D, C>) -> bool {
+        rect.intersects(&self.0)
+    }
+    fn item_matches(&self, rect: &Rect<D, C>, _: &T) -> bool {
+        rect.intersects(&self.0)
+    }
+}
+
+/// Matches the items that are inside of the rect, like
+/// [`RTree::search_within`](super::RTree::search_within).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Within<const D: usize, C: Default>(pub Rect<D, C>);
+
+impl<const D: usize, C, T> SpatialPredicate<D, C, T> for Within<D, C>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    fn node_may_match(&self, rect: &Rect<D, C>) -> bool {
+        rect.intersects(&self.0)
+    }
+    fn item_matches(&self, rect: &Rect<D, C>, _: &T) -> bool {
+        self.0.contains(rect)
+    }
+}
+
+/// Matches the items that contain the rect, like
+/// [`RTree::search_containing`](super::RTree::search_containing). A node can
+/// only hold such items when the node contains the rect too.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Contains<const D: usize, C: Default>(pub Rect<D, C>);
+
+impl<const D: usize, C, T> SpatialPredicate<D, C, T> for Contains<D, C>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    fn node_may_match(&self, rect: &Rect<D, C>) -> bool {
+        rect.contains(&self.0)
+    }
+    fn item_matches(&self, rect: &Rect<D, C>, _: &T) -> bool {
+        rect.contains(&self.0)
+    }
+}
+
+/// Matches the items that the line segment from `a` to `b` passes through,
+/// edges included. Needs no division, so it is exact for integers, which
+/// must be signed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Segment<const D: usize, C> {
+    pub a: [C; D],
+    pub b: [C; D],
+}
+
+impl<const D: usize, C> Segment<D, C>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    pub(crate) fn crosses(&self, rect: &Rect<D, C>) -> bool {
+        // the points of the segment that are inside of the slab of each axis
+        // make an interval of the segment, and the intervals share a point
+        // when every pair of them does. The bounds of the segment check each
+        // interval, and the side of the line that the corners of the rect
+        // are on in each plane checks each pair.
+        let mut bounds = Rect {
+            min: self.a,
+            max: self.b,
+        };
+        for i in 0..D {
+            if bounds.min[i] > bounds.max[i] {
+                bounds.min[i] = self.b[i];
+                bounds.max[i] = self.a[i];
+            }
+        }
+        if !rect.intersects(&bounds) {
+            return false;
+        }
+        for i in 0..D {
+            for j in i + 1..D {
+                let di = self.b[i] - self.a[i];
+                let dj = self.b[j] - self.a[j];
+                let (mut left, mut right) = (false, false);
+                for x in [rect.min[i], rect.max[i]] {
+                    for y in [rect.min[j], rect.max[j]] {
+                        let l = di * (y - self.a[j]);
+                        let r = dj * (x - self.a[i]);
+                        left |= l >= r;
+                        right |= l <= r;
+                    }
+                }
+                if !left || !right {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+impl<const D: usize, C, T> SpatialPredicate<D, C, T> for Segment<D, C>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+{
+    fn node_may_match(&self, rect: &Rect<D, C>) -> bool {
+        self.crosses(rect)
+    }
+    fn item_matches(&self, rect: &Rect<D, C>, _: &T) -> bool {
+        self.crosses(rect)
+    }
+}
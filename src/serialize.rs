@@ -0,0 +1,123 @@
+// Serde support, behind the serde feature. A tree is written out with its
+// whole node structure, so that loading it is O(n) without any splits or
+// reinserts. The plain types derive their impls, and a loaded tree is
+// validated before it's used.
+
+use super::{InsertStrategy, Node, Options, RTree};
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::ops::{Add, Mul, Sub};
+
+// Arrays of any length, as serde only has impls for the lengths up to 32.
+pub(crate) mod coords {
+    use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+    use serde::ser::{Serialize, SerializeTuple, Serializer};
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    pub fn serialize<const D: usize, C, Ser>(
+        coords: &[C; D],
+        serializer: Ser,
+    ) -> Result<Ser::Ok, Ser::Error>
+    where
+        C: Serialize,
+        Ser: Serializer,
+    {
+        let mut tup = serializer.serialize_tuple(D)?;
+        for coord in coords {
+            tup.serialize_element(coord)?;
+        }
+        tup.end()
+    }
+
+    pub fn deserialize<'de, const D: usize, C, De>(deserializer: De) -> Result<[C; D], De::Error>
+    where
+        C: Deserialize<'de> + Copy + Default,
+        De: Deserializer<'de>,
+    {
+        struct CoordsVisitor<const D: usize, C>(PhantomData<C>);
+        impl<'de, const D: usize, C> Visitor<'de> for CoordsVisitor<D, C>
+        where
+            C: Deserialize<'de> + Copy + Default,
+        {
+            type Value = [C; D];
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "an array of {} coordinates", D)
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<[C; D], A::Error> {
+                let mut coords = [C::default(); D];
+                for i in 0..D {
+                    coords[i] = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+                }
+                Ok(coords)
+            }
+        }
+        deserializer.deserialize_tuple(D, CoordsVisitor(PhantomData))
+    }
+}
+
+// The children of a node, as a plain sequence whatever the storage.
+pub(crate) mod nodes {
+    use super::super::Storage;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<K, N, Ser>(nodes: &K::Nodes<N>, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    where
+        K: Storage,
+        N: Serialize,
+        Ser: Serializer,
+    {
+        nodes.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, K, N, De>(deserializer: De) -> Result<K::Nodes<N>, De::Error>
+    where
+        K: Storage,
+        N: Deserialize<'de>,
+        De: Deserializer<'de>,
+    {
+        Ok(K::new_nodes(Vec::deserialize(deserializer)?))
+    }
+}
+
+// The serialized fields of an RTree, which are loaded before the tree is
+// checked.
+#[derive(Deserialize)]
+#[serde(bound = "C: Deserialize<'de>, T: Deserialize<'de>")]
+struct Loaded<const D: usize, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    root: Option<Node<D, C, T>>,
+    len: usize,
+    height: usize,
+    options: Options,
+}
+
+impl<'de, const D: usize, C, T, S> Deserialize<'de> for RTree<D, C, T, S>
+where
+    C: Deserialize<'de>
+        + PartialOrd
+        + Copy
+        + Sub<Output = C>
+        + Add<Output = C>
+        + Mul<Output = C>
+        + Default,
+    T: Deserialize<'de>,
+    S: InsertStrategy + Default,
+{
+    fn deserialize<De: Deserializer<'de>>(
+        deserializer: De,
+    ) -> Result<RTree<D, C, T, S>, De::Error> {
+        let loaded = Loaded::deserialize(deserializer)?;
+        loaded.options.check().map_err(de::Error::custom)?;
+        let mut tr = RTree::with_strategy(loaded.options, S::default());
+        tr.root = loaded.root;
+        tr.length = loaded.len;
+        tr.height = loaded.height;
+        tr.validate().map_err(de::Error::custom)?;
+        Ok(tr)
+    }
+}
@@ -0,0 +1,339 @@
+use super::{compare, max, Options, Rect};
+use std::ops::{Add, Mul, Sub};
+
+/// A child of a node, as seen by a strategy. This is either an item or
+/// another node.
+pub trait Bounded<const D: usize, C: Default> {
+    fn rect(&self) -> &Rect<D, C>;
+}
+
+/// Splits an overflowed node into two nodes.
+pub trait SplitStrategy {
+    /// Splits the children of a node by moving some of them from `left`
+    /// into the empty `right`. The `rect` is the bounds of all children.
+    /// Both sides must end up with at least `opts.min_items` children.
+    fn split<const D: usize, C, E>(
+        &self,
+        rect: &Rect<D, C>,
+        left: &mut Vec<E>,
+        right: &mut Vec<E>,
+        opts: &Options,
+    ) where
+        C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+        E: Bounded<D, C>;
+}
+
+/// Decides where new items go when they are inserted into an RTree.
+pub trait InsertStrategy: SplitStrategy {
+    /// Returns the index of the child that the rect is inserted into. The
+    /// `leaves` flag is true when the children are leaf nodes.
+    ///
+    /// The default chooses the child that needs the least enlargement,
+    /// with ties going to the smallest child.
+    fn choose_subtree<const D: usize, C, E>(
+        &self,
+        children: &[E],
+        rect: &Rect<D, C>,
+        leaves: bool,
+    ) -> usize
+    where
+        C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+        E: Bounded<D, C>,
+    {
+        let _ = leaves;
+        choose_least_enlargement(children, rect)
+    }
+
+    /// Called the first time that a node other than the root overflows on
+    /// its level during a single insert. Any children that are moved into
+    /// `reinsert` are inserted again from the top of the tree, in order,
+    /// and the node is only split when nothing was moved. At least
+    /// `opts.min_items` children must stay behind.
+    ///
+    /// The default moves nothing.
+    fn reinsert<const D: usize, C, E>(
+        &self,
+        rect: &Rect<D, C>,
+        children: &mut Vec<E>,
+        reinsert: &mut Vec<E>,
+        opts: &Options,
+    ) where
+        C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+        E: Bounded<D, C>,
+    {
+        let _ = (rect, children, reinsert, opts);
+    }
+}
+
+/// The default strategy. Least enlargement subtree choice and the largest
+/// axis edge snap split. Fast, and well suited for points and moving data.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EdgeSnap;
+
+impl SplitStrategy for EdgeSnap {
+    fn split<const D: usize, C, E>(
+        &self,
+        rect: &Rect<D, C>,
+        lchilds: &mut Vec<E>,
+        rchilds: &mut Vec<E>,
+        opts: &Options,
+    ) where
+        C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+        E: Bounded<D, C>,
+    {
+        let axis = rect.largest_axis();
+        let mut i = 0;
+        while i < lchilds.len() {
+            let min = lchilds[i].rect().min[axis] - rect.min[axis];
+            let max = rect.max[axis] - lchilds[i].rect().max[axis];
+            if min < max {
+                // stay left
+                i += 1;
+            } else {
+                // move right
+                rchilds.push(lchilds.swap_remove(i));
+            }
+        }
+        // Make sure that both left and right nodes have at least
+        // min_items by moving items into underflowed nodes.
+        if lchilds.len() < opts.min_items {
+            // reverse sort by min axis
+            rchilds.sort_by(|a, b| compare(b.rect().min[axis], a.rect().min[axis]));
+            while lchilds.len() < opts.min_items {
+                lchilds.push(rchilds.pop().unwrap());
+            }
+        } else if rchilds.len() < opts.min_items {
+            // reverse sort by max axis
+            lchilds.sort_by(|a, b| compare(b.rect().max[axis], a.rect().max[axis]));
+            while rchilds.len() < opts.min_items {
+                rchilds.push(lchilds.pop().unwrap());
+            }
+        }
+        // sort the nodes
+        sort_by_axis(lchilds, 0);
+        sort_by_axis(rchilds, 0);
+    }
+}
+
+impl InsertStrategy for EdgeSnap {}
+
+/// The R*-tree algorithm. Subtrees are chosen by the least overlap
+/// enlargement at the leaf level, nodes are split along the axis with the
+/// smallest margin using the distribution with the least overlap, and the
+/// first overflow on each level reinserts the entries farthest from the node
+/// center instead of splitting. Slower inserts, but less node overlap for
+/// rectangle data.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RStar;
+
+// The share of entries, in percent, that an overflow reinserts.
+const RSTAR_REINSERT: usize = 30;
+
+impl SplitStrategy for RStar {
+    // The axis with the smallest sum of margins over all distributions is
+    // chosen, and then the distribution on that axis with the least overlap,
+    // with ties going to the least total area.
+    fn split<const D: usize, C, E>(
+        &self,
+        _rect: &Rect<D, C>,
+        left: &mut Vec<E>,
+        right: &mut Vec<E>,
+        opts: &Options,
+    ) where
+        C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+        E: Bounded<D, C>,
+    {
+        let min_items = opts.min_items;
+        let len = left.len();
+        let mut axis = 0;
+        let mut amargin: C = Default::default();
+        for i in 0..D {
+            let mut margin = Default::default();
+            for by_max in [false, true] {
+                sort_by_edge(left, i, by_max);
+                let (lower, upper) = split_bounds(left);
+                for k in min_items..=len - min_items {
+                    margin = margin + lower[k - 1].margin() + upper[k].margin();
+                }
+            }
+            if i == 0 || margin < amargin {
+                axis = i;
+                amargin = margin;
+            }
+        }
+        let mut best = (false, min_items);
+        let mut boverlap: C = Default::default();
+        let mut barea: C = Default::default();
+        for by_max in [false, true] {
+            sort_by_edge(left, axis, by_max);
+            let (lower, upper) = split_bounds(left);
+            for k in min_items..=len - min_items {
+                let overlap = lower[k - 1].overlap_area(&upper[k]);
+                let area = lower[k - 1].area() + upper[k].area();
+                if (!by_max && k == min_items)
+                    || overlap < boverlap
+                    || (overlap == boverlap && area < barea)
+                {
+                    best = (by_max, k);
+                    boverlap = overlap;
+                    barea = area;
+                }
+            }
+        }
+        sort_by_edge(left, axis, best.0);
+        right.extend(left.drain(best.1..));
+    }
+}
+
+impl InsertStrategy for RStar {
+    fn choose_subtree<const D: usize, C, E>(
+        &self,
+        children: &[E],
+        rect: &Rect<D, C>,
+        leaves: bool,
+    ) -> usize
+    where
+        C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+        E: Bounded<D, C>,
+    {
+        if leaves {
+            choose_least_overlap_enlargement(children, rect)
+        } else {
+            choose_least_enlargement(children, rect)
+        }
+    }
+
+    // Moves the children that are farthest from the center of the node, and
+    // the closest of those are reinserted first.
+    fn reinsert<const D: usize, C, E>(
+        &self,
+        rect: &Rect<D, C>,
+        children: &mut Vec<E>,
+        reinsert: &mut Vec<E>,
+        opts: &Options,
+    ) where
+        C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+        E: Bounded<D, C>,
+    {
+        let count = max(1, opts.max_items * RSTAR_REINSERT / 100);
+        children.sort_by(|a, b| compare(rect.center_dist(a.rect()), rect.center_dist(b.rect())));
+        let start = children.len() - count;
+        reinsert.extend(children.drain(start..));
+    }
+}
+
+fn choose_least_enlargement<const D: usize, C, E>(children: &[E], rect: &Rect<D, C>) -> usize
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    E: Bounded<D, C>,
+{
+    if D == 0 {
+        return 0;
+    }
+    let mut j = 0;
+    let mut jenlargement = rect.min[0];
+    let mut jarea = rect.min[0];
+    for i in 0..children.len() {
+        let uarea = children[i].rect().unioned_area(rect);
+        let area = children[i].rect().area();
+        let enlargement = uarea - area;
+        if i == 0 || enlargement < jenlargement || (enlargement == jenlargement && area < jarea) {
+            j = i;
+            jenlargement = enlargement;
+            jarea = area;
+        }
+    }
+    j
+}
+
+// The child that needs the least overlap enlargement with its siblings is
+// chosen, with ties going to the least area enlargement and then the
+// smallest area.
+fn choose_least_overlap_enlargement<const D: usize, C, E>(
+    children: &[E],
+    rect: &Rect<D, C>,
+) -> usize
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    E: Bounded<D, C>,
+{
+    let mut j = 0;
+    let mut joverlap: C = Default::default();
+    let mut jenlargement: C = Default::default();
+    let mut jarea: C = Default::default();
+    for i in 0..children.len() {
+        let crect = children[i].rect();
+        let mut union = *crect;
+        union.expand(rect);
+        let mut overlap = Default::default();
+        for k in 0..children.len() {
+            if k != i {
+                let before = crect.overlap_area(children[k].rect());
+                overlap = overlap + (union.overlap_area(children[k].rect()) - before);
+            }
+        }
+        let area = crect.area();
+        let enlargement = union.area() - area;
+        if i == 0
+            || overlap < joverlap
+            || (overlap == joverlap
+                && (enlargement < jenlargement || (enlargement == jenlargement && area < jarea)))
+        {
+            j = i;
+            joverlap = overlap;
+            jenlargement = enlargement;
+            jarea = area;
+        }
+    }
+    j
+}
+
+fn sort_by_axis<const D: usize, C, E>(children: &mut [E], axis: usize)
+where
+    C: PartialOrd + Copy + Default,
+    E: Bounded<D, C>,
+{
+    children.sort_by(|a, b| compare(a.rect().min[axis], b.rect().min[axis]));
+}
+
+// Sorts the children by their min edge on the axis, or by their max edge.
+fn sort_by_edge<const D: usize, C, E>(children: &mut [E], axis: usize, by_max: bool)
+where
+    C: PartialOrd + Copy + Default,
+    E: Bounded<D, C>,
+{
+    if by_max {
+        children.sort_by(|a, b| {
+            compare(a.rect().max[axis], b.rect().max[axis])
+                .then_with(|| compare(a.rect().min[axis], b.rect().min[axis]))
+        });
+    } else {
+        children.sort_by(|a, b| {
+            compare(a.rect().min[axis], b.rect().min[axis])
+                .then_with(|| compare(a.rect().max[axis], b.rect().max[axis]))
+        });
+    }
+}
+
+// Returns the bounds of every prefix and every suffix of the children, where
+// lower[i] covers children[..=i] and upper[i] covers children[i..].
+fn split_bounds<const D: usize, C, E>(children: &[E]) -> (Vec<Rect<D, C>>, Vec<Rect<D, C>>)
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    E: Bounded<D, C>,
+{
+    let mut lower = Vec::with_capacity(children.len());
+    let mut upper = Vec::with_capacity(children.len());
+    let mut rect = *children[0].rect();
+    for child in children {
+        rect.expand(child.rect());
+        lower.push(rect);
+    }
+    rect = *children[children.len() - 1].rect();
+    for child in children.iter().rev() {
+        rect.expand(child.rect());
+        upper.push(rect);
+    }
+    upper.reverse();
+    (lower, upper)
+}
@@ -0,0 +1,250 @@
+//! SVG drawings of the node hierarchy, for debugging and tuning.
+//!
+//! ```text
+//! let svg = Svg::new()
+//!     .query(rect)
+//!     .results(tr.search(rect).map(|item| item.rect))
+//!     .render(&tr);
+//! ```
+
+use super::{Data, Node, RTree, Rect};
+use std::fmt::Write;
+use std::ops::{Add, Mul, Sub};
+
+const COLORS: [&str; 4] = ["red", "#009900", "#cccc00", "purple"];
+
+/// A coordinate that can be drawn. It's converted to an `f64` with `as`, so
+/// large integers lose precision.
+pub trait ToF64: Copy {
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! impl_to_f64 {
+    ($($t:ty),*) => {
+        $(
+            impl ToF64 for $t {
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+        )*
+    };
+}
+
+impl_to_f64!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+
+/// Draws a tree as an SVG. Nodes are outlined in the color of their depth,
+/// items are drawn as points or outlines, and query rects and result items
+/// can be drawn on top. The view box fits everything that is drawn, and the
+/// y axis points up.
+///
+/// Trees with more than two dimensions are projected onto two of the axes.
+#[derive(Clone, Debug)]
+pub struct Svg<const D: usize, C: Default> {
+    colors: Vec<String>,
+    item_color: String,
+    query_color: String,
+    result_color: String,
+    axes: (usize, usize),
+    queries: Vec<Rect<D, C>>,
+    results: Vec<Rect<D, C>>,
+}
+
+impl<const D: usize, C> Default for Svg<D, C>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    C: ToF64,
+{
+    fn default() -> Svg<D, C> {
+        Svg::new()
+    }
+}
+
+impl<const D: usize, C> Svg<D, C>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    C: ToF64,
+{
+    pub fn new() -> Svg<D, C> {
+        Svg {
+            colors: COLORS.iter().map(|color| color.to_string()).collect(),
+            item_color: "black".to_string(),
+            query_color: "blue".to_string(),
+            result_color: "orange".to_string(),
+            axes: (0, 1),
+            queries: Vec::new(),
+            results: Vec::new(),
+        }
+    }
+    /// The colors of the nodes, by depth from the root. They repeat when
+    /// the tree is deeper.
+    pub fn colors<S: AsRef<str>>(mut self, colors: &[S]) -> Svg<D, C> {
+        self.colors = colors
+            .iter()
+            .map(|color| color.as_ref().to_string())
+            .collect();
+        self
+    }
+    pub fn item_color(mut self, color: &str) -> Svg<D, C> {
+        self.item_color = color.to_string();
+        self
+    }
+    pub fn query_color(mut self, color: &str) -> Svg<D, C> {
+        self.query_color = color.to_string();
+        self
+    }
+    pub fn result_color(mut self, color: &str) -> Svg<D, C> {
+        self.result_color = color.to_string();
+        self
+    }
+    /// The axes that are drawn as x and y. Defaults to the first two.
+    pub fn axes(mut self, x: usize, y: usize) -> Svg<D, C> {
+        self.axes = (x, y);
+        self
+    }
+    /// Draws a query rect over the tree.
+    pub fn query(mut self, rect: Rect<D, C>) -> Svg<D, C> {
+        self.queries.push(rect);
+        self
+    }
+    /// Draws result items over the tree, such as the rects of a search.
+    pub fn results<I: IntoIterator<Item = Rect<D, C>>>(mut self, rects: I) -> Svg<D, C> {
+        self.results.extend(rects);
+        self
+    }
+    /// Returns the drawing of the tree.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an axis is not below `D`.
+    pub fn render<T, S>(&self, tr: &RTree<D, C, T, S>) -> String {
+        let (x, y) = self.axes;
+        assert!(x < D && y < D, "axes ({}, {}) out of range", x, y);
+        let mut bounds = tr.root.as_ref().map(|root| root.rect);
+        for rect in self.queries.iter().chain(self.results.iter()) {
+            match &mut bounds {
+                Some(bounds) => bounds.expand(rect),
+                None => bounds = Some(*rect),
+            }
+        }
+        // the view box, with some room around the edges
+        let (mut min, mut max) = ([0.0, 0.0], [1.0, 1.0]);
+        if let Some(bounds) = bounds {
+            min = [bounds.min[x].to_f64(), -bounds.max[y].to_f64()];
+            max = [bounds.max[x].to_f64(), -bounds.min[y].to_f64()];
+        }
+        let mut size = f64::max(max[0] - min[0], max[1] - min[1]);
+        if size <= 0.0 {
+            size = 1.0;
+        }
+        let pad = size / 40.0;
+        let mut canvas = Canvas {
+            out: String::new(),
+            axes: self.axes,
+            stroke: size / 1000.0,
+        };
+        writeln!(
+            canvas.out,
+            "<svg viewBox=\"{} {} {} {}\" xmlns=\"http://www.w3.org/2000/svg\">",
+            min[0] - pad,
+            min[1] - pad,
+            max[0] - min[0] + pad * 2.0,
+            max[1] - min[1] + pad * 2.0,
+        )
+        .unwrap();
+        if let Some(root) = &tr.root {
+            self.node(&mut canvas, root, 0);
+        }
+        for rect in &self.results {
+            canvas.item(rect, &self.result_color, 2.0);
+        }
+        for rect in &self.queries {
+            canvas.rect(rect, &self.query_color, 2.0, true);
+        }
+        canvas.out += "</svg>\n";
+        canvas.out
+    }
+    fn node<T>(&self, canvas: &mut Canvas, node: &Node<D, C, T>, depth: usize) {
+        match &node.data {
+            Data::Nodes(nodes) => {
+                let color = match self.colors.len() {
+                    0 => COLORS[depth % COLORS.len()],
+                    n => &self.colors[depth % n],
+                };
+                canvas.rect(&node.rect, color, 1.0, false);
+                for node in nodes.iter() {
+                    self.node(canvas, node, depth + 1);
+                }
+            }
+            Data::Item(_) => canvas.item(&node.rect, &self.item_color, 1.0),
+        }
+    }
+}
+
+// The output of a drawing, with the y axis flipped so that it points up.
+struct Canvas {
+    out: String,
+    axes: (usize, usize),
+    stroke: f64,
+}
+
+impl Canvas {
+    fn rect<const D: usize, C>(&mut self, rect: &Rect<D, C>, color: &str, width: f64, dashed: bool)
+    where
+        C: Copy + Default + ToF64,
+    {
+        let (x, y) = self.axes;
+        let (min_x, max_x) = (rect.min[x].to_f64(), rect.max[x].to_f64());
+        let (min_y, max_y) = (rect.min[y].to_f64(), rect.max[y].to_f64());
+        writeln!(
+            self.out,
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" stroke=\"{}\" \
+             fill-opacity=\"0\" stroke-width=\"{}\"{}/>",
+            min_x,
+            -max_y,
+            max_x - min_x,
+            max_y - min_y,
+            color,
+            self.stroke * width,
+            if dashed {
+                format!(" stroke-dasharray=\"{}\"", self.stroke * 4.0)
+            } else {
+                String::new()
+            },
+        )
+        .unwrap();
+    }
+    // Points are drawn as dots, and anything larger as an outline.
+    fn item<const D: usize, C>(&mut self, rect: &Rect<D, C>, color: &str, scale: f64)
+    where
+        C: Copy + Default + ToF64,
+    {
+        let (x, y) = self.axes;
+        if rect.min[x].to_f64() != rect.max[x].to_f64()
+            || rect.min[y].to_f64() != rect.max[y].to_f64()
+        {
+            self.rect(rect, color, scale, false);
+            return;
+        }
+        writeln!(
+            self.out,
+            "<circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\" stroke-width=\"0\"/>",
+            rect.min[x].to_f64(),
+            -rect.min[y].to_f64(),
+            self.stroke * scale,
+            color,
+        )
+        .unwrap();
+    }
+}
+
+impl<const D: usize, C, T, S> RTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    C: ToF64,
+{
+    /// Returns a drawing of the tree with the default [`Svg`] settings.
+    pub fn svg(&self) -> String {
+        Svg::new().render(self)
+    }
+}
@@ -3,18 +3,18 @@
 #![allow(unused_imports)]
 
 use super::*;
+#[allow(clippy::single_component_path_imports)]
 use rand;
 
 fn point2(x: f64, y: f64) -> super::Rect<2, f64> {
     Rect::new_point([x, y])
 }
 
-use std::fmt::Display;
 use std::fs::File;
 use std::io::{Error, Write};
 
-fn test_rtree(count: usize, points_only: bool) {
-    let mut tr = RTree::new();
+fn test_rtree<S: InsertStrategy>(count: usize, points_only: bool, opts: Options, strategy: S) {
+    let mut tr = RTree::with_strategy(opts, strategy);
     let mut pts = vec![];
     for _ in 0..count {
         let xy = [
@@ -38,6 +38,7 @@ fn test_rtree(count: usize, points_only: bool) {
         // search for this item
         assert_eq!(tr.search(pts[i]).filter(|x| x.data == &i).count(), 1);
     }
+    tr.validate().unwrap();
     // scan all rects and compare
     let mut all: Vec<IterItem<2, f64, usize>> = tr.scan().collect();
     all.sort_by(|a, b| a.data.cmp(b.data));
@@ -79,69 +80,1803 @@ fn test_rtree(count: usize, points_only: bool) {
         assert_eq!(tr.len(), pts.len() - i - 1);
         // search for this item
         assert_eq!(tr.search(pts[i]).filter(|x| x.data == &i).count(), 0);
+        if i % 10_000 == 0 {
+            tr.validate().unwrap();
+        }
     }
+    tr.validate().unwrap();
 }
 
 #[test]
 fn points() {
-    test_rtree(100_000, true);
+    test_rtree(100_000, true, Options::default(), EdgeSnap);
 }
 
 #[test]
 fn rects() {
-    test_rtree(100_000, false);
-}
-
-impl<const D: usize, C, T: PartialEq> Node<D, C, T>
-where
-    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Display + Default,
-{
-    fn svg(&self, depth: usize) -> String {
-        let mut out = String::new();
-        if let Data::Nodes(nodes) = &self.data {
-            out += &format!(
-                "<rect x=\"{}\" y=\"{}\" width=\"{}\" \
-                       height=\"{}\" stroke=\"{}\" fill-opacity=\"0\" \
-                       stroke-opacity=\"1\" stroke-width=\"0.4\"/>\n",
-                self.rect.min[0],
-                self.rect.min[1],
-                self.rect.max[0] - self.rect.min[0],
-                self.rect.max[1] - self.rect.min[1],
-                ["red", "#009900", "#cccc00", "purple"][depth % 4]
-            );
-            for i in 0..nodes.len() {
-                out += &nodes[i].svg(depth + 1);
+    test_rtree(100_000, false, Options::default(), EdgeSnap);
+}
+
+fn test_bulk_load<const D: usize, S: InsertStrategy + Copy>(
+    count: usize,
+    points_only: bool,
+    packing: Packing,
+    opts: Options,
+    strategy: S,
+) {
+    let mut items = vec![];
+    for i in 0..count {
+        let mut min = [0.0; D];
+        let mut max = [0.0; D];
+        for j in 0..D {
+            min[j] = rand::random::<f64>() * 360.0 - 180.0;
+            max[j] = min[j];
+            if !points_only {
+                max[j] += rand::random::<f64>();
+            }
+        }
+        items.push((Rect::new(min, max), i));
+    }
+    let mut tr = RTree::bulk_load_with_strategy(items.clone(), packing, opts, strategy);
+    assert_eq!(tr.len(), count);
+    tr.validate().unwrap();
+    // search for each item
+    for (rect, i) in &items {
+        assert_eq!(tr.search(*rect).filter(|x| x.data == i).count(), 1);
+    }
+    // compare a window search with a brute force
+    let window = Rect::new([-50.0; D], [50.0; D]);
+    let expect = items.iter().filter(|x| x.0.intersects(&window)).count();
+    assert_eq!(tr.search(window).count(), expect);
+    // the packed tree must keep working under insert and remove
+    let mut more = vec![];
+    for i in count..count + count / 2 {
+        let rect = Rect::new_point([rand::random::<f64>() * 360.0 - 180.0; D]);
+        tr.insert(rect, i);
+        more.push((rect, i));
+    }
+    assert_eq!(tr.len(), count + more.len());
+    assert_eq!(
+        tr.nearby(|rect, _| rect.box_dist(&window)).count(),
+        tr.len()
+    );
+    for (rect, i) in items.iter().chain(more.iter()) {
+        let res = tr.remove(*rect, i).unwrap();
+        assert!(res.0 == *rect && res.1 == *i);
+    }
+    assert_eq!(tr.len(), 0);
+    assert!(tr.rect().is_none());
+}
+
+#[test]
+fn bulk_load() {
+    test_bulk_load::<2, _>(100_000, true, Packing::Str, Options::default(), EdgeSnap);
+    test_bulk_load::<2, _>(100_000, false, Packing::Str, Options::default(), EdgeSnap);
+    test_bulk_load::<3, _>(50_000, false, Packing::Str, Options::default(), EdgeSnap);
+    test_bulk_load::<1, _>(10_000, false, Packing::Str, Options::default(), EdgeSnap);
+    for count in 0..100 {
+        test_bulk_load::<2, _>(count, false, Packing::Str, Options::default(), EdgeSnap);
+    }
+}
+
+#[test]
+fn bulk_load_hilbert() {
+    test_bulk_load::<2, _>(
+        100_000,
+        true,
+        Packing::Hilbert,
+        Options::default(),
+        EdgeSnap,
+    );
+    test_bulk_load::<2, _>(
+        100_000,
+        false,
+        Packing::Hilbert,
+        Options::default(),
+        EdgeSnap,
+    );
+    test_bulk_load::<3, _>(
+        50_000,
+        false,
+        Packing::Hilbert,
+        Options::default(),
+        EdgeSnap,
+    );
+    for count in 0..100 {
+        test_bulk_load::<2, _>(count, false, Packing::Hilbert, Options::default(), EdgeSnap);
+    }
+    // integer coordinates
+    let items: Vec<_> = (0..1000u32)
+        .map(|i| (Rect::new_point([i % 40, i / 40]), i))
+        .collect();
+    let tr = RTree::bulk_load_with(items, Packing::Hilbert, Options::default());
+    assert_eq!(tr.search(Rect::new([10, 10], [19, 19])).count(), 100);
+
+    // integers at the ends of their range, where doubling overflows. The
+    // removes reinsert in Hilbert order too.
+    let items: Vec<_> = (0..1000i32)
+        .map(|i| (Rect::new_point([i32::MAX - i % 40, i32::MIN + i / 40]), i))
+        .collect();
+    let mut tr = RTree::bulk_load_with(items.clone(), Packing::Hilbert, Options::default());
+    tr.validate().unwrap();
+    for (rect, i) in items.iter().step_by(2) {
+        assert_eq!(tr.remove(*rect, i), Some((*rect, *i)));
+    }
+    tr.validate().unwrap();
+    for (rect, i) in &items {
+        assert_eq!(tr.search(*rect).count(), (*i % 2) as usize);
+    }
+    let items: Vec<_> = (0..1000i32)
+        .map(|i| (Rect::new_point([i * (i32::MAX / 1000), -i]), i))
+        .collect();
+    let tr = RTree::bulk_load_with(items, Packing::Hilbert, Options::default());
+    tr.validate().unwrap();
+    assert_eq!(tr.len(), 1000);
+}
+
+#[test]
+fn fanout() {
+    for (max_items, min_items) in [(3, 1), (4, 2), (8, 2), (16, 4), (64, 12)] {
+        let opts = Options {
+            max_items,
+            min_items,
+        };
+        test_rtree(2_000, false, opts, EdgeSnap);
+        test_bulk_load::<2, _>(2_000, false, Packing::Str, opts, EdgeSnap);
+        test_bulk_load::<3, _>(2_000, false, Packing::Hilbert, opts, EdgeSnap);
+        for count in 0..20 {
+            test_bulk_load::<2, _>(count, false, Packing::Str, opts, EdgeSnap);
+        }
+    }
+}
+
+#[test]
+fn fanout_refill() {
+    // with a min_items of one, a tree may be emptied while it's still tall
+    let pts = random_points(2_000);
+    let mut tr = RTree::with_options(Options {
+        max_items: 3,
+        min_items: 1,
+    });
+    for i in 0..pts.len() {
+        tr.insert(pts[i], i);
+    }
+    for i in 0..pts.len() {
+        tr.remove(pts[i], &i).unwrap();
+    }
+    assert!(tr.is_empty());
+    assert_eq!(tr.height, 0);
+    tr.insert(pts[0], 0);
+    assert_eq!(tr.search(pts[0]).count(), 1);
+    tr.validate().unwrap();
+}
+
+#[test]
+fn rstar() {
+    let opts = Options::default();
+    test_rtree(20_000, true, opts, RStar);
+    test_rtree(20_000, false, opts, RStar);
+    test_bulk_load::<3, _>(10_000, false, Packing::Str, opts, RStar);
+    for (max_items, min_items) in [(4, 2), (8, 3), (16, 6)] {
+        let opts = Options {
+            max_items,
+            min_items,
+        };
+        test_rtree(2_000, false, opts, RStar);
+    }
+}
+
+// Guttman's quadratic split, as an example of a strategy that lives outside
+// of the crate.
+#[derive(Clone, Copy)]
+struct Quadratic;
+
+impl SplitStrategy for Quadratic {
+    fn split<const D: usize, C, E>(
+        &self,
+        _rect: &Rect<D, C>,
+        left: &mut Vec<E>,
+        right: &mut Vec<E>,
+        opts: &Options,
+    ) where
+        C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+        E: Bounded<D, C>,
+    {
+        // pick the two seeds that waste the most area together
+        let mut seeds = (0, 1);
+        let mut worst = None;
+        for i in 0..left.len() {
+            for j in i + 1..left.len() {
+                let mut union = *left[i].rect();
+                union.expand(left[j].rect());
+                let waste = union.area() - left[i].rect().area() - left[j].rect().area();
+                if worst.is_none_or(|worst| waste > worst) {
+                    worst = Some(waste);
+                    seeds = (i, j);
+                }
             }
+        }
+        let mut rest = std::mem::take(left);
+        right.push(rest.swap_remove(seeds.1));
+        left.push(rest.swap_remove(seeds.0));
+        let mut lrect = *left[0].rect();
+        let mut rrect = *right[0].rect();
+        while let Some(child) = rest.pop() {
+            let to_left = if left.len() + rest.len() < opts.min_items {
+                true
+            } else if right.len() + rest.len() < opts.min_items {
+                false
+            } else {
+                let lgrow = lrect.unioned_area(child.rect()) - lrect.area();
+                let rgrow = rrect.unioned_area(child.rect()) - rrect.area();
+                lgrow < rgrow || (lgrow == rgrow && left.len() < right.len())
+            };
+            if to_left {
+                lrect.expand(child.rect());
+                left.push(child);
+            } else {
+                rrect.expand(child.rect());
+                right.push(child);
+            }
+        }
+    }
+}
+
+impl InsertStrategy for Quadratic {}
+
+#[test]
+fn custom_strategy() {
+    test_rtree(10_000, false, Options::default(), Quadratic);
+    let opts = Options {
+        max_items: 8,
+        min_items: 3,
+    };
+    test_rtree(2_000, true, opts, Quadratic);
+    test_bulk_load::<2, _>(2_000, false, Packing::Hilbert, opts, Quadratic);
+}
+
+// Strategies that break the rules. LopsidedSplit moves a single child on a
+// split, and GreedyReinsert keeps only one child on a reinsert.
+struct LopsidedSplit;
+
+impl SplitStrategy for LopsidedSplit {
+    fn split<const D: usize, C, E>(
+        &self,
+        _rect: &Rect<D, C>,
+        left: &mut Vec<E>,
+        right: &mut Vec<E>,
+        _opts: &Options,
+    ) where
+        C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+        E: Bounded<D, C>,
+    {
+        right.push(left.pop().unwrap());
+    }
+}
+
+impl InsertStrategy for LopsidedSplit {}
+
+struct GreedyReinsert;
+
+impl SplitStrategy for GreedyReinsert {
+    fn split<const D: usize, C, E>(
+        &self,
+        rect: &Rect<D, C>,
+        left: &mut Vec<E>,
+        right: &mut Vec<E>,
+        opts: &Options,
+    ) where
+        C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+        E: Bounded<D, C>,
+    {
+        EdgeSnap.split(rect, left, right, opts);
+    }
+}
+
+impl InsertStrategy for GreedyReinsert {
+    fn reinsert<const D: usize, C, E>(
+        &self,
+        _rect: &Rect<D, C>,
+        children: &mut Vec<E>,
+        reinsert: &mut Vec<E>,
+        _opts: &Options,
+    ) where
+        C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+        E: Bounded<D, C>,
+    {
+        reinsert.extend(children.drain(1..));
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "split off a side with too few children")]
+fn bad_split_strategy() {
+    let mut tr = RTree::with_strategy(Options::default(), LopsidedSplit);
+    for (i, pt) in random_points(100).into_iter().enumerate() {
+        tr.insert(pt, i);
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "reinserted too many children")]
+fn bad_reinsert_strategy() {
+    let mut tr = RTree::with_strategy(Options::default(), GreedyReinsert);
+    for (i, pt) in random_points(2_000).into_iter().enumerate() {
+        tr.insert(pt, i);
+    }
+}
+
+// Data that cannot be compared, which is only removable by predicate.
+struct Payload {
+    id: usize,
+    _buf: Vec<u8>,
+}
+
+#[test]
+fn remove_by() {
+    let opts = Options {
+        max_items: 8,
+        min_items: 2,
+    };
+    let pts = random_points(500);
+    let mut tr = RTree::with_options(opts);
+    for (i, pt) in pts.iter().enumerate() {
+        tr.insert(
+            *pt,
+            Payload {
+                id: i,
+                _buf: vec![0; i % 7],
+            },
+        );
+    }
+    assert!(tr.remove_by(pts[0], |item| item.id == 1).is_none());
+    assert_eq!(tr.len(), pts.len());
+    for i in (0..pts.len()).step_by(2) {
+        let (rect, data) = tr.remove_by(pts[i], |item| item.id == i).unwrap();
+        assert!(rect == pts[i]);
+        assert_eq!(data.id, i);
+        assert!(tr.remove_by(pts[i], |item| item.id == i).is_none());
+    }
+    assert_eq!(tr.len(), pts.len() / 2);
+    tr.validate().unwrap();
+    for i in 0..pts.len() {
+        let count = tr.search(pts[i]).filter(|x| x.data.id == i).count();
+        assert_eq!(count, i % 2);
+    }
+}
+
+#[test]
+fn retain_and_drain() {
+    let opts = Options {
+        max_items: 8,
+        min_items: 3,
+    };
+    let mut tr = RTree::with_options(opts);
+    let mut rects = vec![];
+    for (i, pt) in random_points(1_000).into_iter().enumerate() {
+        rects.push(Rect::new(pt.min, [pt.min[0] + 1.0, pt.min[1] + 1.0]));
+        tr.insert(rects[i], i);
+    }
+
+    // drain the even items in a region
+    let target = Rect::new([-90.0, -45.0], [90.0, 45.0]);
+    let mut drained: Vec<(Rect<2, f64>, usize)> =
+        tr.drain_in(target, |_, data| data % 2 == 0).collect();
+    drained.sort_by_key(|item| item.1);
+    let expect: Vec<usize> = (0..rects.len())
+        .filter(|&i| i % 2 == 0 && rects[i].intersects(&target))
+        .collect();
+    assert_eq!(
+        drained.iter().map(|item| item.1).collect::<Vec<_>>(),
+        expect
+    );
+    for (rect, i) in &drained {
+        assert!(*rect == rects[*i]);
+    }
+    assert_eq!(tr.len(), rects.len() - expect.len());
+    tr.validate().unwrap();
+    assert_eq!(tr.search(target).filter(|x| x.data % 2 == 0).count(), 0);
+
+    // keep everything that is not a multiple of three
+    tr.retain(|_, data| data % 3 != 0);
+    let expect: Vec<usize> = (0..rects.len())
+        .filter(|&i| i % 3 != 0 && !(i % 2 == 0 && rects[i].intersects(&target)))
+        .collect();
+    let mut all: Vec<usize> = tr.iter().map(|item| *item.data).collect();
+    all.sort();
+    assert_eq!(all, expect);
+    assert_eq!(tr.len(), expect.len());
+    tr.validate().unwrap();
+
+    // drain everything
+    let everything = tr.rect().unwrap();
+    assert_eq!(tr.drain_in(everything, |_, _| true).count(), expect.len());
+    assert!(tr.is_empty());
+    assert!(tr.rect().is_none());
+    assert_eq!(tr.drain_in(everything, |_, _| true).count(), 0);
+    tr.insert(rects[0], 0);
+    assert_eq!(tr.search(rects[0]).count(), 1);
+}
+
+#[test]
+fn iter_mut() {
+    let pts = random_points(1_000);
+    let mut tr = RTree::new();
+    for (i, pt) in pts.iter().enumerate() {
+        tr.insert(*pt, (i, 0));
+    }
+
+    // bump every item
+    let mut count = 0;
+    for item in tr.iter_mut() {
+        item.data.1 += 1;
+        count += 1;
+    }
+    assert_eq!(count, pts.len());
+    assert!(tr.iter().all(|item| item.data.1 == 1));
+
+    // bump the items in a region
+    let target = Rect::new([-90.0, -45.0], [90.0, 45.0]);
+    for item in tr.search_mut(target) {
+        assert!(item.rect.intersects(&target));
+        item.data.1 += 1;
+    }
+    for item in tr.iter() {
+        let (i, n) = *item.data;
+        assert!(item.rect == pts[i]);
+        assert_eq!(n, if pts[i].intersects(&target) { 2 } else { 1 });
+    }
+
+    // zero the nearest items
+    let origin = Rect::new_point([0.0, 0.0]);
+    let mut dist = 0.0;
+    for item in tr.nearby_mut(|rect, _| rect.box_dist(&origin)).take(100) {
+        assert!(item.dist >= dist);
+        dist = item.dist;
+        item.data.1 = 0;
+    }
+    let near: Vec<usize> = tr
+        .nearby(|rect, _| rect.box_dist(&origin))
+        .take(100)
+        .map(|item| item.data.1)
+        .collect();
+    assert_eq!(near, vec![0; 100]);
+    assert_eq!(tr.iter().filter(|item| item.data.1 == 0).count(), 100);
+}
+
+#[test]
+fn update() {
+    let opts = Options {
+        max_items: 8,
+        min_items: 2,
+    };
+    let mut pts = random_points(500);
+    let mut tr = RTree::with_options(opts);
+    for (i, pt) in pts.iter().enumerate() {
+        tr.insert(*pt, i);
+    }
+    assert!(!tr.update(pts[0], &pts.len(), pts[0]));
+    for round in 0..20 {
+        for i in 0..pts.len() {
+            // mostly small moves, and an occasional jump
+            let scale = if (i + round) % 50 == 0 { 100.0 } else { 0.5 };
+            let xy = [
+                pts[i].min[0] + (rand::random::<f64>() - 0.5) * scale,
+                pts[i].min[1] + (rand::random::<f64>() - 0.5) * scale,
+            ];
+            let rect = Rect::new_point(xy);
+            assert!(tr.update(pts[i], &i, rect));
+            pts[i] = rect;
+        }
+        assert_eq!(tr.len(), pts.len());
+        tr.validate().unwrap();
+    }
+    for i in 0..pts.len() {
+        assert_eq!(tr.search(pts[i]).filter(|x| x.data == &i).count(), 1);
+    }
+    let all: Vec<Rect<2, f64>> = tr.iter().map(|item| item.rect).collect();
+    let mut rect = all[0];
+    for r in &all {
+        rect.expand(r);
+    }
+    assert!(tr.rect().unwrap() == rect);
+}
+
+#[test]
+fn update_equal_data() {
+    // two items with the same data in one leaf, only the one at the old
+    // rect is moved
+    let mut tr = RTree::new();
+    tr.insert(Rect::new_point([0.0, 0.0]), 1);
+    tr.insert(Rect::new_point([5.0, 5.0]), 1);
+    tr.insert(Rect::new_point([10.0, 10.0]), 2);
+    assert!(tr.update(Rect::new_point([5.0, 5.0]), &1, Rect::new_point([6.0, 6.0])));
+    let mut rects: Vec<[f64; 2]> = tr
+        .iter()
+        .filter(|item| item.data == &1)
+        .map(|item| item.rect.min)
+        .collect();
+    rects.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(rects, vec![[0.0, 0.0], [6.0, 6.0]]);
+    tr.validate().unwrap();
+}
+
+#[test]
+fn collect_and_into_iter() {
+    let items: Vec<(Rect<2, f64>, usize)> = random_points(5_000).into_iter().zip(0..).collect();
+
+    // a large collect is bulk loaded
+    let tr: RTree<2, f64, usize> = items.iter().cloned().collect();
+    assert_eq!(tr.len(), items.len());
+    tr.validate().unwrap();
+    let packed = RTree::bulk_load(items.clone());
+    assert_eq!(tr.height, packed.height);
+    assert_eq!(tr.root.unwrap().len(), packed.root.unwrap().len());
+
+    // a small one is inserted
+    let mut tr: RTree<2, f64, usize> = items[..10].iter().cloned().collect();
+    assert_eq!(tr.len(), 10);
+    tr.extend(items[10..].iter().cloned());
+    assert_eq!(tr.len(), items.len());
+    tr.validate().unwrap();
+
+    // extend an empty tree that uses another strategy
+    let mut rtr = RTree::with_strategy(Options::default(), RStar);
+    rtr.extend(items.iter().cloned());
+    assert_eq!(rtr.len(), items.len());
+
+    // consume the tree
+    let mut iter = tr.into_iter();
+    assert_eq!(iter.len(), items.len());
+    iter.next().unwrap();
+    assert_eq!(iter.len(), items.len() - 1);
+    let mut all: Vec<(Rect<2, f64>, usize)> = iter.collect();
+    assert_eq!(all.len(), items.len() - 1);
+    all.sort_by_key(|item| item.1);
+    all.dedup_by_key(|item| item.1);
+    assert_eq!(all.len(), items.len() - 1);
+    for (rect, i) in all {
+        assert!(rect == items[i].0);
+    }
+    assert_eq!(RTree::<2, f64, usize>::new().into_iter().count(), 0);
+
+    // dropping a partly consumed iterator frees the rest
+    let mut iter = rtr.into_iter();
+    iter.next().unwrap();
+    drop(iter);
+}
+
+#[test]
+fn clone_and_debug() {
+    let mut tr = RTree::new();
+    for i in 0..1_000 {
+        tr.insert(Rect::new_point([i as f64, (i % 100) as f64]), i);
+    }
+    let snap = tr.clone();
+    tr.retain(|_, data| data % 2 == 0);
+    tr.update(
+        Rect::new_point([0.0, 0.0]),
+        &0,
+        Rect::new_point([-1.0, -1.0]),
+    );
+    assert_eq!(tr.len(), 500);
+    assert_eq!(snap.len(), 1_000);
+    snap.validate().unwrap();
+    assert_eq!(snap.search(Rect::new_point([0.0, 0.0])).count(), 1);
+    assert_eq!(snap.iter().filter(|x| x.data % 2 == 1).count(), 500);
+
+    let rect = Rect::new([1, 2], [3, 4]);
+    assert_eq!(format!("{:?}", rect), "Rect { min: [1, 2], max: [3, 4] }");
+    let mut tr = RTree::new();
+    assert_eq!(
+        format!("{:?}", tr),
+        "RTree { height: 0, len: 0, rect: None }"
+    );
+    tr.insert(rect, "A");
+    assert_eq!(
+        format!("{:?}", tr),
+        "RTree { height: 0, len: 1, rect: Some(Rect { min: [1, 2], max: [3, 4] }) }"
+    );
+    assert_eq!(
+        format!("{:?}", tr.iter().next().unwrap()),
+        "IterItem { rect: Rect { min: [1, 2], max: [3, 4] }, data: \"A\", dist: 0 }"
+    );
+    let dump = format!("{:#?}", tr);
+    assert!(dump.contains("root: Some(\n        Node {\n"));
+    assert!(dump.contains("Item {\n"));
+    assert!(dump.contains("data: \"A\",\n"));
+
+    // every item and branch shows up in the dump
+    let dump = format!("{:#?}", snap);
+    assert_eq!(dump.matches("Item {").count(), 1_000);
+    assert!(dump.matches("Node {").count() > 1_000 / MAX_ITEMS);
+}
+
+fn random_points(count: usize) -> Vec<Rect<2, f64>> {
+    let mut pts = vec![];
+    for _ in 0..count {
+        let xy = [
+            rand::random::<f64>() * 360.0 - 180.0,
+            rand::random::<f64>() * 180.0 - 90.0,
+        ];
+        pts.push(Rect::new_point(xy));
+    }
+    pts
+}
+
+#[test]
+fn persistent() {
+    let pts = random_points(5_000);
+    let opts = Options {
+        max_items: 8,
+        min_items: 2,
+    };
+    for i in 0..2 {
+        let mut tr = if i == 0 {
+            PersistentRTree::with_options(opts)
         } else {
-            out += &format!(
-                "<circle cx=\"{}\" cy=\"{}\" r=\"0.2\" \
-                         fill=\"{}\" stroke-width=\"0\"/>\n",
-                self.rect.min[0], self.rect.min[1], "black"
-            );
-        }
-        return out;
-    }
-}
-
-impl<const D: usize, C, T: PartialEq> RTree<D, C, T>
-where
-    C: PartialOrd + Copy + Sub<Output = C> + Mul<Output = C> + Display + Default,
-{
-    pub fn svg(&self) -> String {
-        let mut out = String::new();
-        out += &format!(
-            "<svg viewBox=\"{} {} {} {}\" \
-                  xmlns =\"http://www.w3.org/2000/svg\">\n",
-            -190.0, -100.0, 380.0, 190.0,
+            PersistentRTree::from(RTree::bulk_load_with(vec![], Packing::Str, opts))
+        };
+        let mut snaps = vec![];
+        for i in 0..pts.len() {
+            if i % 1_000 == 0 {
+                snaps.push((i, tr.snapshot()));
+            }
+            tr.insert(pts[i], i);
+            assert_eq!(tr.search(pts[i]).filter(|x| x.data == &i).count(), 1);
+        }
+        assert_eq!(tr.len(), pts.len());
+        assert_eq!(tr.iter().count(), pts.len());
+
+        // remove every other item
+        snaps.push((pts.len(), tr.snapshot()));
+        for i in (0..pts.len()).step_by(2) {
+            let (rect, data) = tr.remove(pts[i], &i).unwrap();
+            assert!(rect == pts[i]);
+            assert_eq!(data, i);
+            assert!(tr.remove(pts[i], &i).is_none());
+        }
+        assert_eq!(tr.len(), pts.len() / 2);
+        for i in 0..pts.len() {
+            assert_eq!(tr.search(pts[i]).filter(|x| x.data == &i).count(), i % 2);
+        }
+
+        // every snapshot still holds exactly what was inserted before it
+        for (len, snap) in &snaps {
+            assert_eq!(snap.len(), *len);
+            let mut all: Vec<usize> = snap.iter().map(|x| *x.data).collect();
+            all.sort();
+            assert_eq!(all, (0..*len).collect::<Vec<_>>());
+            for i in 0..*len {
+                assert_eq!(snap.search(pts[i]).filter(|x| x.data == &i).count(), 1);
+            }
+            let target = Rect::new_point([0.0, 0.0]);
+            let mut dist = 0.0;
+            let mut count = 0;
+            for item in snap.nearby(|rect, _| rect.box_dist(&target)) {
+                assert!(item.dist >= dist);
+                dist = item.dist;
+                count += 1;
+            }
+            assert_eq!(count, *len);
+        }
+
+        // drain a snapshot, which leaves the tree alone
+        let mut snap = tr.snapshot();
+        for i in (1..pts.len()).step_by(2) {
+            assert!(snap.remove_by(pts[i], |data| *data == i).is_some());
+        }
+        assert!(snap.is_empty());
+        assert!(snap.rect().is_none());
+        assert_eq!(tr.len(), pts.len() / 2);
+        assert_eq!(tr.iter().count(), pts.len() / 2);
+    }
+}
+
+#[test]
+fn persistent_from_rtree() {
+    let pts = random_points(5_000);
+    let items: Vec<(Rect<2, f64>, usize)> = pts.iter().cloned().zip(0..).collect();
+    let tr = RTree::bulk_load_with_strategy(items, Packing::Hilbert, Options::default(), RStar);
+    let rect = tr.rect().unwrap();
+    let mut ptr = PersistentRTree::from(tr);
+    assert!(ptr.rect().unwrap() == rect);
+    assert_eq!(ptr.len(), pts.len());
+    let snap = ptr.snapshot();
+    for i in 0..pts.len() {
+        ptr.insert(pts[i], pts.len() + i);
+    }
+    assert_eq!(ptr.len(), pts.len() * 2);
+    assert_eq!(snap.len(), pts.len());
+    for i in 0..pts.len() {
+        assert_eq!(snap.search(pts[i]).count(), 1);
+        assert_eq!(ptr.search(pts[i]).count(), 2);
+    }
+}
+
+#[test]
+fn persistent_readers() {
+    let pts = random_points(2_000);
+    let mut tr = PersistentRTree::new();
+    for i in 0..pts.len() {
+        tr.insert(pts[i], i);
+    }
+    let snap = tr.snapshot();
+    std::thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for i in 0..pts.len() {
+                    assert_eq!(snap.search(pts[i]).filter(|x| x.data == &i).count(), 1);
+                }
+            });
+        }
+        for i in 0..pts.len() {
+            tr.remove(pts[i], &i).unwrap();
+        }
+    });
+    assert!(tr.is_empty());
+    assert_eq!(snap.len(), pts.len());
+}
+
+fn assert_send_sync<T: Send + Sync>() {}
+fn assert_send<T: Send>() {}
+
+// Only compiles when T is not Send, because the call is ambiguous between
+// the two impls otherwise.
+trait NotSend<A> {
+    fn check() {}
+}
+impl<T: ?Sized> NotSend<()> for T {}
+impl<T: ?Sized + Send> NotSend<u8> for T {}
+
+#[test]
+fn send_sync() {
+    type Dist = fn(Rect<2, f64>, Option<&usize>) -> f64;
+    assert_send_sync::<Node<2, f64, usize>>();
+    assert_send_sync::<RTree<2, f64, usize>>();
+    assert_send_sync::<RTree<3, i64, String, RStar>>();
+    assert_send_sync::<ScanIterator<'static, 2, f64, usize>>();
+    assert_send_sync::<SearchIterator<'static, 2, f64, usize>>();
+    assert_send_sync::<SearchWithIterator<'static, 2, f64, usize, Segment<2, f64>>>();
+    assert_send_sync::<SearchRadiusIterator<'static, 2, f64, usize>>();
+    assert_send_sync::<NearbyIterator<'static, 2, f64, usize, Dist>>();
+    assert_send_sync::<RaycastIterator<'static, 2, f64, usize>>();
+    assert_send_sync::<SearchPolygonIterator<'static, f64, usize>>();
+    assert_send_sync::<ScanIteratorMut<'static, 2, f64, usize>>();
+    assert_send_sync::<SearchIteratorMut<'static, 2, f64, usize>>();
+    assert_send_sync::<NearbyIteratorMut<'static, 2, f64, usize, Dist>>();
+    assert_send_sync::<DrainIterator<2, f64, usize>>();
+    assert_send_sync::<IntoIter<2, f64, usize>>();
+    assert_send_sync::<PersistentRTree<2, f64, usize>>();
+    assert_send_sync::<ConcurrentRTree<2, f64, usize>>();
+    assert_send_sync::<ConcurrentRTree<2, f64, String, RStar>>();
+
+    assert_send_sync::<ScanIterator<'static, 2, f64, usize, Shared>>();
+    assert_send_sync::<NearbyIterator<'static, 2, f64, usize, Dist, Shared>>();
+
+    // data that is Send but not Sync keeps an RTree Send, along with the
+    // iterators that own or write the items, but not the iterators that
+    // share the items, or a PersistentRTree, which shares its nodes
+    type Unsync = std::cell::Cell<usize>;
+    type UnsyncDist = fn(Rect<2, f64>, Option<&Unsync>) -> f64;
+    assert_send::<Node<2, f64, Unsync>>();
+    assert_send::<RTree<2, f64, Unsync>>();
+    assert_send::<RTree<3, i64, Unsync, RStar>>();
+    assert_send::<ScanIteratorMut<'static, 2, f64, Unsync>>();
+    assert_send::<SearchIteratorMut<'static, 2, f64, Unsync>>();
+    assert_send::<NearbyIteratorMut<'static, 2, f64, Unsync, UnsyncDist>>();
+    assert_send::<DrainIterator<2, f64, Unsync>>();
+    assert_send::<IntoIter<2, f64, Unsync>>();
+    <ScanIterator<'static, 2, f64, Unsync> as NotSend<_>>::check();
+    <SearchIterator<'static, 2, f64, Unsync> as NotSend<_>>::check();
+    <PersistentRTree<2, f64, Unsync> as NotSend<_>>::check();
+}
+
+#[test]
+fn concurrent() {
+    let pts = random_points(2_000);
+    let tr = ConcurrentRTree::new();
+    let half = pts.len() / 2;
+    tr.write(|tr| {
+        for i in 0..half {
+            tr.insert(pts[i], i);
+        }
+    });
+    std::thread::scope(|scope| {
+        // readers always see whole batches
+        for _ in 0..4 {
+            scope.spawn(|| {
+                for _ in 0..20 {
+                    tr.search(Rect::new([-180.0, -90.0], [180.0, 90.0]), |iter| {
+                        assert_eq!(iter.count() % 100, 0);
+                    });
+                    tr.scan(|iter| assert_eq!(iter.count() % 100, 0));
+                    let origin = Rect::new_point([0.0, 0.0]);
+                    tr.nearby(
+                        |rect, _| rect.box_dist(&origin),
+                        |mut iter| {
+                            let first = iter.next().map_or(0.0, |item| item.dist);
+                            assert!(iter.all(|item| item.dist >= first));
+                        },
+                    );
+                    let snap = tr.snapshot();
+                    let len = snap.len();
+                    assert_eq!(len % 100, 0);
+                    assert_eq!(snap.iter().count(), len);
+                    let origin = Rect::new_point([0.0, 0.0]);
+                    let count = snap.nearby(|rect, _| rect.box_dist(&origin)).count();
+                    assert_eq!(count, len);
+                    for item in snap.search(Rect::new([-180.0, -90.0], [180.0, 90.0])) {
+                        assert!(*item.data < pts.len());
+                    }
+                }
+            });
+        }
+        // the writer adds and removes items in batches of 100
+        for batch in (half..pts.len()).step_by(100) {
+            tr.write(|tr| {
+                for i in batch..batch + 100 {
+                    tr.insert(pts[i], i);
+                }
+            });
+        }
+        for batch in (0..half).step_by(100) {
+            let removed = tr.write(|tr| {
+                (batch..batch + 100)
+                    .filter(|&i| tr.remove(pts[i], &i).is_some())
+                    .count()
+            });
+            assert_eq!(removed, 100);
+        }
+    });
+    assert_eq!(tr.len(), half);
+    let snap = tr.snapshot();
+    for i in 0..pts.len() {
+        let count = snap.search(pts[i]).filter(|x| x.data == &i).count();
+        assert_eq!(count, if i < half { 0 } else { 1 });
+    }
+
+    // a single change, and a panicked batch that is thrown away
+    tr.insert(pts[0], 0);
+    assert_eq!(tr.len(), half + 1);
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        tr.write(|tr| {
+            tr.insert(pts[1], 1);
+            panic!("oops");
+        })
+    }));
+    assert!(res.is_err());
+    assert_eq!(tr.len(), half + 1);
+    tr.write(|tr| assert_eq!(tr.len(), half + 1));
+    assert!(tr.remove(pts[0], &0).is_some());
+    assert!(tr.remove_by(pts[1], |data| *data == 1).is_none());
+    assert_eq!(tr.len(), half);
+}
+
+#[test]
+fn mapped() {
+    let pts = random_points(5_000);
+    let mut trees = vec![
+        RTree::new(),
+        RTree::bulk_load((0..pts.len()).map(|i| (pts[i], i)).collect()),
+    ];
+    for i in 0..pts.len() {
+        trees[0].insert(pts[i], i);
+    }
+    for tr in &trees {
+        let mut buf = Vec::new();
+        tr.write_mapped(&mut buf, |data, buf| {
+            buf.extend_from_slice(format!("item {}", data).as_bytes())
+        })
+        .unwrap();
+        let mapped: MappedRTree<2, f64> = MappedRTree::open(&buf).unwrap();
+        assert_eq!(mapped.len(), pts.len());
+        assert!(mapped.rect() == tr.rect());
+        assert_eq!(mapped.iter().count(), pts.len());
+
+        // same items, in the same order, as the tree
+        let to_str = |data: &[u8]| String::from_utf8(data.to_vec()).unwrap();
+        let target = Rect::new([-20.0, -10.0], [20.0, 10.0]);
+        let expect: Vec<_> = tr
+            .search(target)
+            .map(|x| (x.rect, format!("item {}", x.data)))
+            .collect();
+        let got: Vec<_> = mapped
+            .search(target)
+            .map(|x| (x.rect, to_str(x.data)))
+            .collect();
+        assert!(!expect.is_empty());
+        assert!(expect == got);
+        for i in (0..pts.len()).step_by(100) {
+            let items: Vec<_> = mapped.search(pts[i]).map(|x| to_str(x.data)).collect();
+            assert!(items.contains(&format!("item {}", i)));
+        }
+
+        let target = Rect::new_point([0.0, 0.0]);
+        let expect: Vec<f64> = tr
+            .nearby(|rect, _| rect.box_dist(&target))
+            .map(|x| x.dist)
+            .collect();
+        let got: Vec<f64> = mapped
+            .nearby(|rect, _| rect.box_dist(&target))
+            .map(|x| x.dist)
+            .collect();
+        assert_eq!(expect, got);
+
+        // wrong dimensions, truncated bytes and bad versions are rejected
+        assert!(MappedRTree::<3, f64>::open(&buf).is_err());
+        assert!(MappedRTree::<2, f32>::open(&buf).is_err());
+        assert!(MappedRTree::<2, f64>::open(&buf[..buf.len() - 1]).is_err());
+        let mut bad = buf.clone();
+        bad[4] = 2;
+        assert!(MappedRTree::<2, f64>::open(&bad).is_err());
+
+        // corrupt sections fail to open, rather than panic when searched
+        let invalid = |buf: &[u8]| {
+            let err = MappedRTree::<2, f64>::open(buf).err().unwrap();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        };
+        for cut in (0..buf.len()).step_by(97) {
+            invalid(&buf[..cut]);
+        }
+        let read = |offset: usize| u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap());
+        let (nodes, entries, items) = (read(24), read(32), read(40));
+        let nodes_at = 56 + 32;
+        let refs_at = nodes_at + nodes as usize * 16 + entries as usize * 32;
+        let offsets_at = refs_at + entries as usize * 8;
+        let corrupt = |offset: usize, value: u64| {
+            let mut bad = buf.clone();
+            bad[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+            invalid(&bad);
+        };
+        // a node with more entries than there are, or that overlaps another
+        corrupt(nodes_at + 8, entries + 1);
+        corrupt(nodes_at + 16, 0);
+        // a node referred to twice, or a node or item that does not exist
+        corrupt(refs_at, 0);
+        corrupt(refs_at, nodes);
+        corrupt(refs_at + (entries as usize - 1) * 8, items);
+        corrupt(refs_at + (entries as usize - 1) * 8, u64::MAX);
+        // data out of order, or past the end of the data
+        corrupt(offsets_at + 8, read(offsets_at + 16) + 1);
+        corrupt(offsets_at + items as usize * 8, u64::MAX);
+    }
+
+    let mut buf = Vec::new();
+    RTree::<2, i32, ()>::new()
+        .write_mapped(&mut buf, |_, _| {})
+        .unwrap();
+    let mapped = MappedRTree::<2, i32>::open(&buf).unwrap();
+    assert!(mapped.is_empty());
+    assert!(mapped.rect().is_none());
+    assert_eq!(mapped.search(Rect::new([0, 0], [10, 10])).count(), 0);
+    assert_eq!(mapped.nearby(|_, _| 0).count(), 0);
+}
+
+#[test]
+fn validate() {
+    let pts = random_points(5_000);
+    let opts = Options {
+        max_items: 8,
+        min_items: 2,
+    };
+    let mut tr = RTree::with_options(opts);
+    assert_eq!(tr.validate(), Ok(()));
+    for i in 0..pts.len() {
+        tr.insert(pts[i], i);
+    }
+    tr.validate().unwrap();
+    tr.retain(|_, data| data % 3 != 0);
+    tr.validate().unwrap();
+    for packing in [Packing::Str, Packing::Hilbert] {
+        for count in [0, 1, 7, 8, 9, 100, 5_000] {
+            let items = (0..count).map(|i| (pts[i], i)).collect();
+            RTree::bulk_load_with(items, packing, opts)
+                .validate()
+                .unwrap();
+        }
+    }
+
+    // the path leads to the first broken node
+    let path = |tr: &RTree<2, f64, usize>| tr.validate().unwrap_err().path;
+    let mut broken = tr.clone();
+    broken.root.as_mut().unwrap().nodes_mut()[1].nodes_mut()[0]
+        .rect
+        .max[0] += 1.0;
+    let err = broken.validate().unwrap_err();
+    assert_eq!(err.kind, Violation::Rect);
+    assert_eq!(err.path, vec![1, 0]);
+    assert!(err.to_string().ends_with("at [1, 0]"));
+
+    let mut broken = tr.clone();
+    let node = &mut broken.root.as_mut().unwrap().nodes_mut()[1];
+    while node.len() > 1 {
+        node.nodes_mut().pop();
+    }
+    node.recalc();
+    broken.root.as_mut().unwrap().recalc();
+    assert_eq!(broken.validate().unwrap_err().kind, Violation::Fanout(1));
+    assert_eq!(path(&broken), vec![1]);
+
+    let mut broken = tr.clone();
+    broken.height += 1;
+    assert_eq!(broken.validate().unwrap_err().kind, Violation::Height);
+    assert_eq!(path(&broken).len(), tr.height + 1);
+
+    let mut broken = tr.clone();
+    broken.length += 1;
+    assert_eq!(
+        broken.validate().unwrap_err().kind,
+        Violation::Length(tr.len())
+    );
+    assert!(path(&broken).is_empty());
+}
+
+#[test]
+fn stats() {
+    let opts = Options {
+        max_items: 4,
+        min_items: 2,
+    };
+    let mut tr = RTree::with_options(opts);
+    assert_eq!(tr.stats(), Stats::default());
+    tr.insert(Rect::new([0, 0], [1, 1]), 1);
+    tr.insert(Rect::new([2, 2], [3, 3]), 2);
+    tr.insert(Rect::new([0, 2], [1, 3]), 3);
+    let stats = tr.stats();
+    assert_eq!(stats.levels.len(), 1);
+    assert_eq!(stats.nodes, 1);
+    assert_eq!(stats.fill_factor, 1.0);
+    assert_eq!(stats.overlap, 0);
+    assert_eq!(stats.dead_space, 6);
+    assert_eq!(stats.margin, 6);
+    assert!(stats.memory >= 3 * std::mem::size_of::<Node<2, i32, i32>>());
+
+    // overlapping siblings cover their shared space once
+    let mut tr = RTree::with_options(opts);
+    tr.insert(Rect::new([0, 0], [2, 2]), 1);
+    tr.insert(Rect::new([1, 1], [3, 3]), 2);
+    let stats = tr.stats();
+    assert_eq!(stats.overlap, 0);
+    assert_eq!(stats.dead_space, 9 - 7);
+
+    // the leaves hold every item, and the levels add up
+    let pts = random_points(10_000);
+    for tr in [
+        pts.iter()
+            .enumerate()
+            .map(|(i, pt)| (*pt, i))
+            .collect::<RTree<2, f64, usize>>(),
+        RTree::bulk_load(pts.iter().enumerate().map(|(i, pt)| (*pt, i)).collect()),
+    ] {
+        let stats = tr.stats();
+        let capacity = (tr.options().max_items - 1) as f64;
+        assert_eq!(stats.levels.len(), tr.height + 1);
+        assert_eq!(stats.levels[0].nodes, 1);
+        assert_eq!(stats.levels[0].overlap, 0.0);
+        let leaves = stats.levels.last().unwrap();
+        let items = leaves.fill_factor * leaves.nodes as f64 * capacity;
+        assert!((items - pts.len() as f64).abs() < 1e-6);
+        assert_eq!(
+            stats.nodes,
+            stats.levels.iter().map(|x| x.nodes).sum::<usize>()
         );
-        out += "<g transform=\"scale(1,-1)\">\n";
-        if let Some(root) = &self.root {
-            out += &root.svg(0);
+        assert!(stats.fill_factor > 0.0 && stats.fill_factor <= 1.0);
+        assert!(stats.overlap >= 0.0 && stats.dead_space > 0.0 && stats.margin > 0.0);
+        assert!(stats.memory >= pts.len() * std::mem::size_of::<Node<2, f64, usize>>());
+    }
+}
+
+#[test]
+fn search_within_and_containing() {
+    let mut rects = vec![];
+    for _ in 0..5_000 {
+        let x = rand::random::<f64>() * 360.0 - 180.0;
+        let y = rand::random::<f64>() * 180.0 - 90.0;
+        let w = rand::random::<f64>() * 20.0;
+        let h = rand::random::<f64>() * 20.0;
+        rects.push(Rect::new([x, y], [x + w, y + h]));
+    }
+    let tr: RTree<2, f64, usize> = (0..rects.len()).map(|i| (rects[i], i)).collect();
+    for _ in 0..100 {
+        let x = rand::random::<f64>() * 360.0 - 180.0;
+        let y = rand::random::<f64>() * 180.0 - 90.0;
+        for query in [
+            Rect::new([x, y], [x + 40.0, y + 20.0]),
+            Rect::new([x, y], [x + 1.0, y + 1.0]),
+            Rect::new_point([x, y]),
+        ] {
+            let mut within: Vec<usize> = tr.search_within(query).map(|x| *x.data).collect();
+            within.sort();
+            let expect: Vec<usize> = (0..rects.len())
+                .filter(|&i| query.contains(&rects[i]))
+                .collect();
+            assert_eq!(within, expect);
+
+            let mut containing: Vec<usize> = tr.search_containing(query).map(|x| *x.data).collect();
+            containing.sort();
+            let expect: Vec<usize> = (0..rects.len())
+                .filter(|&i| rects[i].contains(&query))
+                .collect();
+            assert_eq!(containing, expect);
+        }
+    }
+    // edges count as inside
+    let corner = Rect::new_point([rects[0].max[0], rects[0].min[1]]);
+    assert!(tr.search_containing(corner).any(|x| *x.data == 0));
+    assert!(tr.search_within(rects[0]).any(|x| *x.data == 0));
+}
+
+// Items whose rect intersects a circle, and whose data is even.
+struct EvenInCircle {
+    center: [f64; 2],
+    radius: f64,
+}
+
+impl EvenInCircle {
+    fn reaches(&self, rect: &Rect<2, f64>) -> bool {
+        let mut dist = 0.0;
+        for i in 0..2 {
+            let d = (rect.min[i] - self.center[i])
+                .max(self.center[i] - rect.max[i])
+                .max(0.0);
+            dist += d * d;
+        }
+        dist <= self.radius * self.radius
+    }
+}
+
+impl SpatialPredicate<2, f64, usize> for EvenInCircle {
+    fn node_may_match(&self, rect: &Rect<2, f64>) -> bool {
+        self.reaches(rect)
+    }
+    fn item_matches(&self, rect: &Rect<2, f64>, data: &usize) -> bool {
+        data.is_multiple_of(2) && self.reaches(rect)
+    }
+}
+
+#[test]
+fn search_with() {
+    let pts = random_points(5_000);
+    let tr: RTree<2, f64, usize> = (0..pts.len()).map(|i| (pts[i], i)).collect();
+    for _ in 0..100 {
+        let x = rand::random::<f64>() * 360.0 - 180.0;
+        let y = rand::random::<f64>() * 180.0 - 90.0;
+        let query = Rect::new([x, y], [x + 10.0, y + 10.0]);
+        let mut found: Vec<usize> = tr.search_with(Intersects(query)).map(|x| *x.data).collect();
+        let mut expect: Vec<usize> = tr.search(query).map(|x| *x.data).collect();
+        found.sort();
+        expect.sort();
+        assert_eq!(found, expect);
+
+        let pred = EvenInCircle {
+            center: [x, y],
+            radius: 10.0,
+        };
+        let mut found: Vec<usize> = tr.search_with(pred).map(|x| *x.data).collect();
+        found.sort();
+        let pred = EvenInCircle {
+            center: [x, y],
+            radius: 10.0,
+        };
+        let expect: Vec<usize> = (0..pts.len())
+            .filter(|&i| pred.item_matches(&pts[i], &i))
+            .collect();
+        assert_eq!(found, expect);
+    }
+}
+
+#[test]
+fn search_radius() {
+    let pts = random_points(5_000);
+    let tr: RTree<2, f64, usize> = (0..pts.len()).map(|i| (pts[i], i)).collect();
+    for _ in 0..100 {
+        let center = [
+            rand::random::<f64>() * 360.0 - 180.0,
+            rand::random::<f64>() * 180.0 - 90.0,
+        ];
+        let radius = rand::random::<f64>() * 20.0;
+        let mut found = vec![];
+        for item in tr.search_radius(center, radius) {
+            assert_eq!(item.dist, item.rect.box_dist(&Rect::new_point(center)));
+            assert!(item.dist <= radius * radius);
+            found.push(*item.data);
+        }
+        found.sort();
+        let expect: Vec<usize> = (0..pts.len())
+            .filter(|&i| pts[i].box_dist(&Rect::new_point(center)) <= radius * radius)
+            .collect();
+        assert_eq!(found, expect);
+    }
+
+    // any number of dimensions, and integers too
+    let mut tr: RTree<3, i64, usize> = RTree::new();
+    let mut rects = vec![];
+    for i in 0..1_000 {
+        let min = [
+            rand::random::<i64>() % 100,
+            rand::random::<i64>() % 100,
+            rand::random::<i64>() % 100,
+        ];
+        let rect = Rect::new(min, [min[0] + 3, min[1] + 3, min[2] + 3]);
+        rects.push(rect);
+        tr.insert(rect, i);
+    }
+    let center = Rect::new_point([0, 0, 0]);
+    let mut found: Vec<usize> = tr.search_radius([0, 0, 0], 30).map(|x| *x.data).collect();
+    found.sort();
+    let expect: Vec<usize> = (0..rects.len())
+        .filter(|&i| rects[i].box_dist(&center) <= 900)
+        .collect();
+    assert_eq!(found, expect);
+    assert!(!found.is_empty());
+    assert_eq!(
+        tr.search_radius([0, 0, 0], 0).count(),
+        tr.search(center).count()
+    );
+    assert_eq!(tr.search_radius([0, 0, 0], -30).count(), 0);
+}
+
+#[test]
+fn raycast_and_segment() {
+    let mut rects = vec![];
+    for _ in 0..5_000 {
+        let min = [
+            rand::random::<f64>() * 100.0,
+            rand::random::<f64>() * 100.0,
+            rand::random::<f64>() * 100.0,
+        ];
+        let size = rand::random::<f64>() * 2.0;
+        rects.push(Rect::new(
+            min,
+            [min[0] + size, min[1] + size, min[2] + size],
+        ));
+    }
+    let tr: RTree<3, f64, usize> = (0..rects.len()).map(|i| (rects[i], i)).collect();
+    for _ in 0..100 {
+        let origin = [
+            rand::random::<f64>() * 100.0,
+            rand::random::<f64>() * 100.0,
+            rand::random::<f64>() * 100.0,
+        ];
+        let dir = [
+            rand::random::<f64>() * 2.0 - 1.0,
+            rand::random::<f64>() * 2.0 - 1.0,
+            0.0,
+        ];
+        let max_t = rand::random::<f64>() * 100.0;
+        let mut last = 0.0;
+        let mut hits = vec![];
+        for item in tr.raycast(origin, dir, max_t) {
+            assert!(item.dist >= last && item.dist <= max_t);
+            last = item.dist;
+            hits.push(*item.data);
+        }
+        hits.sort();
+        let expect: Vec<usize> = (0..rects.len())
+            .filter(|&i| rects[i].ray_entry(&origin, &dir, max_t).is_some())
+            .collect();
+        assert_eq!(hits, expect);
+        if let Some(first) = tr.raycast(origin, dir, max_t).next() {
+            let nearest = expect
+                .iter()
+                .map(|&i| rects[i].ray_entry(&origin, &dir, max_t).unwrap())
+                .fold(f64::INFINITY, f64::min);
+            assert_eq!(first.dist, nearest);
+        }
+
+        // a segment is a ray that stops at the end
+        let b = [
+            origin[0] + dir[0] * max_t,
+            origin[1] + dir[1] * max_t,
+            origin[2] + dir[2] * max_t,
+        ];
+        let mut found: Vec<usize> = tr.search_segment(origin, b).map(|x| *x.data).collect();
+        found.sort();
+        let dir = [b[0] - origin[0], b[1] - origin[1], b[2] - origin[2]];
+        let expect: Vec<usize> = (0..rects.len())
+            .filter(|&i| rects[i].ray_entry(&origin, &dir, 1.0).is_some())
+            .collect();
+        assert_eq!(found, expect);
+    }
+
+    // f32 works too, including rects behind the origin, which are missed
+    let mut tr: RTree<2, f32, &str> = RTree::new();
+    tr.insert(Rect::new([2.0, -1.0], [3.0, 1.0]), "far");
+    tr.insert(Rect::new([0.5, -1.0], [1.0, 1.0]), "near");
+    tr.insert(Rect::new([-0.5, -1.0], [-0.25, 1.0]), "behind");
+    let hits: Vec<(&str, f32)> = tr
+        .raycast([0.0, 0.0], [1.0, 0.0], 10.0)
+        .map(|x| (*x.data, x.dist))
+        .collect();
+    assert_eq!(hits, vec![("near", 0.5), ("far", 2.0)]);
+
+    // exact for integers, with edges and corners included
+    let mut tr: RTree<2, i64, &str> = RTree::new();
+    tr.insert(Rect::new([0, 0], [10, 10]), "square");
+    tr.insert(Rect::new([20, 0], [30, 10]), "right");
+    tr.insert(Rect::new_point([15, 15]), "point");
+    let search = |a, b| {
+        let mut found: Vec<&str> = tr.search_segment(a, b).map(|x| *x.data).collect();
+        found.sort();
+        found
+    };
+    assert_eq!(search([-5, 5], [35, 5]), vec!["right", "square"]);
+    assert_eq!(search([10, 20], [20, 10]), vec!["point", "right"]);
+    assert_eq!(search([0, 21], [21, 0]), vec!["right"]);
+    assert_eq!(search([11, 20], [20, 11]), Vec::<&str>::new());
+    assert_eq!(search([5, 5], [5, 5]), vec!["square"]);
+    assert_eq!(search([-10, -1], [40, 11]), vec!["right", "square"]);
+    assert_eq!(search([0, 11], [40, 19]), Vec::<&str>::new());
+}
+
+// A point in polygon test with division, to check the one without.
+fn in_polygon(polygon: &[[f64; 2]], point: [f64; 2]) -> bool {
+    let mut inside = false;
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+        if (a[1] <= point[1]) != (b[1] <= point[1]) {
+            let x = a[0] + (point[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]);
+            if x > point[0] {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+#[test]
+fn search_polygon() {
+    let pts = random_points(10_000);
+    let tr: RTree<2, f64, usize> = (0..pts.len()).map(|i| (pts[i], i)).collect();
+    let mut rects = vec![];
+    for _ in 0..5_000 {
+        let x = rand::random::<f64>() * 360.0 - 180.0;
+        let y = rand::random::<f64>() * 180.0 - 90.0;
+        let size = rand::random::<f64>() * 5.0;
+        rects.push(Rect::new([x, y], [x + size, y + size]));
+    }
+    let rtr: RTree<2, f64, usize> = (0..rects.len()).map(|i| (rects[i], i)).collect();
+    for _ in 0..50 {
+        // a star shaped lasso, which is concave
+        let center = [
+            rand::random::<f64>() * 300.0 - 150.0,
+            rand::random::<f64>() * 120.0 - 60.0,
+        ];
+        let mut polygon = vec![];
+        for i in 0..24 {
+            let angle = i as f64 / 24.0 * std::f64::consts::TAU;
+            let radius = 5.0 + rand::random::<f64>() * 30.0;
+            polygon.push([
+                center[0] + angle.cos() * radius,
+                center[1] + angle.sin() * radius,
+            ]);
+        }
+        let mut found = vec![];
+        for item in tr.search_polygon(&polygon) {
+            assert!(item.inside);
+            found.push(*item.data);
+        }
+        found.sort();
+        let expect: Vec<usize> = (0..pts.len())
+            .filter(|&i| in_polygon(&polygon, pts[i].min))
+            .collect();
+        assert_eq!(found, expect);
+
+        // rects are inside when all of their corners are, and are found
+        // when any of them is
+        let mut found = vec![false; rects.len()];
+        for item in rtr.search_polygon(&polygon) {
+            let rect = rects[*item.data];
+            let corners = [
+                rect.min,
+                rect.max,
+                [rect.min[0], rect.max[1]],
+                [rect.max[0], rect.min[1]],
+            ];
+            let count = corners.iter().filter(|&&c| in_polygon(&polygon, c)).count();
+            if count < 4 {
+                assert!(!item.inside);
+            }
+            found[*item.data] = true;
+        }
+        for i in 0..rects.len() {
+            if in_polygon(&polygon, rects[i].min) {
+                assert!(found[i]);
+            }
+        }
+    }
+
+    // exact for integers, with a notch in the polygon
+    let polygon = [
+        [0, 0],
+        [30, 0],
+        [30, 30],
+        [20, 30],
+        [20, 10],
+        [10, 10],
+        [10, 30],
+        [0, 30],
+    ];
+    let rects = [
+        Rect::new([12, 12], [18, 18]),
+        Rect::new([12, 5], [18, 8]),
+        Rect::new([5, 5], [25, 20]),
+        Rect::new([10, 10], [20, 30]),
+        Rect::new([0, 0], [30, 10]),
+        Rect::new_point([20, 20]),
+        Rect::new_point([15, 20]),
+        Rect::new([40, 40], [50, 50]),
+        Rect::new([-5, -5], [35, 35]),
+        Rect::new([10, 20], [20, 20]),
+        Rect::new([0, 20], [10, 20]),
+        Rect::new([30, 30], [40, 40]),
+    ];
+    let tr: RTree<2, i64, usize> = (0..rects.len()).map(|i| (rects[i], i)).collect();
+    let mut found: Vec<(usize, bool)> = tr
+        .search_polygon(&polygon)
+        .map(|x| (*x.data, x.inside))
+        .collect();
+    found.sort();
+    assert_eq!(
+        found,
+        vec![
+            (1, true),
+            (2, false),
+            (3, false),
+            (4, true),
+            (5, true),
+            (8, false),
+            (9, false),
+            (10, true),
+            (11, false),
+        ]
+    );
+    assert_eq!(tr.search_polygon(&[]).count(), 0);
+}
+
+#[test]
+fn paged() {
+    let pts = random_points(5_000);
+    let opts = Options {
+        max_items: 16,
+        min_items: 4,
+    };
+    assert!(
+        PagedRTree::<2, f64, u64, MemoryPageStore>::create(MemoryPageStore::new(256), opts)
+            .is_err()
+    );
+    let path = std::env::temp_dir().join(format!("rtree-paged-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    for i in 0..2 {
+        let store: Box<dyn PageStore> = if i == 0 {
+            Box::new(MemoryPageStore::new(1024))
+        } else {
+            Box::new(FilePageStore::open(&path, 1024).unwrap())
+        };
+        let mut tr: PagedRTree<2, f64, u64, _> = PagedRTree::create(store, opts).unwrap();
+        // a small cache, so that pages are evicted and read back
+        tr.set_cache_size(8).unwrap();
+        for i in 0..pts.len() {
+            tr.insert(pts[i], i as u64).unwrap();
+        }
+        assert_eq!(tr.len(), pts.len());
+        for i in (0..pts.len()).step_by(10) {
+            let found = tr
+                .search(pts[i])
+                .filter(|x| x.as_ref().unwrap().data == i as u64);
+            assert_eq!(found.count(), 1);
         }
-        out += "</g>\n";
-        out += "</svg>\n";
-        out
+
+        // remove every other item
+        for i in (0..pts.len()).step_by(2) {
+            let (rect, data) = tr.remove(pts[i], &(i as u64)).unwrap().unwrap();
+            assert!(rect == pts[i]);
+            assert_eq!(data, i as u64);
+            assert!(tr.remove(pts[i], &(i as u64)).unwrap().is_none());
+        }
+        assert_eq!(tr.len(), pts.len() / 2);
+
+        // reopen the store, and everything is still there
+        let store = tr.into_store().unwrap();
+        let mut tr: PagedRTree<2, f64, u64, _> = PagedRTree::open(store).unwrap();
+        assert_eq!(tr.len(), pts.len() / 2);
+        let everything = Rect::new([-180.0, -90.0], [180.0, 90.0]);
+        let mut all: Vec<u64> = tr.search(everything).map(|x| x.unwrap().data).collect();
+        all.sort();
+        assert_eq!(all, (1..pts.len() as u64).step_by(2).collect::<Vec<_>>());
+        let target = Rect::new_point([0.0, 0.0]);
+        let mut dist = 0.0;
+        let mut count = 0;
+        for item in tr.nearby(|rect, _| rect.box_dist(&target)) {
+            let item = item.unwrap();
+            assert!(item.dist >= dist);
+            dist = item.dist;
+            count += 1;
+        }
+        assert_eq!(count, pts.len() / 2);
+
+        // freed pages are reused
+        let pages = tr.page_count();
+        for i in (0..pts.len()).step_by(2) {
+            tr.insert(pts[i], i as u64).unwrap();
+        }
+        assert!(tr.page_count() < pages + pages / 2);
+        for i in 0..pts.len() {
+            assert!(tr.remove(pts[i], &(i as u64)).unwrap().is_some());
+        }
+        assert!(tr.is_empty());
+        assert!(tr.rect().unwrap().is_none());
+        assert_eq!(tr.search(everything).count(), 0);
     }
+    assert!(PagedRTree::<3, f64, u64, _>::open(FilePageStore::open(&path, 1024).unwrap()).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn wal() {
+    fn items(tr: &LoggedRTree<2, f64, u64>) -> Vec<(Rect<2, f64>, u64)> {
+        assert_eq!(tr.tree().iter().count(), tr.len());
+        let mut items: Vec<_> = tr.tree().iter().map(|x| (x.rect, *x.data)).collect();
+        for (rect, data) in &items {
+            assert!(tr.tree().search(*rect).any(|x| x.data == data));
+        }
+        items.sort_by_key(|x| x.1);
+        items
+    }
+    let dir = std::env::temp_dir().join(format!("rtree-wal-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let pts = random_points(300);
+    let mut tr: LoggedRTree<2, f64, u64> = LoggedRTree::open(&dir).unwrap();
+    for i in 0..100 {
+        tr.insert(pts[i], i as u64).unwrap();
+    }
+    let old_wal = std::fs::read(dir.join("wal")).unwrap();
+    tr.checkpoint().unwrap();
+
+    // the state after every change, and where its record ends
+    let mut model: Vec<(Rect<2, f64>, u64)> = items(&tr);
+    let mut states = vec![(
+        std::fs::metadata(dir.join("wal")).unwrap().len(),
+        model.clone(),
+    )];
+    for i in 100..250 {
+        match i % 5 {
+            0 | 1 => {
+                tr.insert(pts[i], i as u64).unwrap();
+                model.push((pts[i], i as u64));
+            }
+            2 => {
+                let (rect, data) = model.remove(i % model.len());
+                assert!(tr.remove(rect, &data).unwrap().is_some());
+            }
+            3 => {
+                let j = i % model.len();
+                assert!(tr.update(model[j].0, &model[j].1, pts[i]).unwrap());
+                model[j].0 = pts[i];
+            }
+            _ => {
+                assert!(tr.remove(pts[i], &(i as u64)).unwrap().is_none());
+            }
+        }
+        model.sort_by_key(|x| x.1);
+        assert!(items(&tr) == model);
+        states.push((
+            std::fs::metadata(dir.join("wal")).unwrap().len(),
+            model.clone(),
+        ));
+    }
+    drop(tr);
+    let wal = std::fs::read(dir.join("wal")).unwrap();
+    assert_eq!(wal.len() as u64, states.last().unwrap().0);
+
+    // cut the log off at every offset, and the tree holds every change that
+    // was completely logged before the cut
+    for cut in 0..=wal.len() {
+        std::fs::write(dir.join("wal"), &wal[..cut]).unwrap();
+        let tr: LoggedRTree<2, f64, u64> = LoggedRTree::open(&dir).unwrap();
+        let (end, state) = states
+            .iter()
+            .rev()
+            .find(|(end, _)| *end <= cut as u64)
+            .unwrap_or(&states[0]);
+        assert!(items(&tr) == *state);
+        drop(tr);
+        assert_eq!(std::fs::metadata(dir.join("wal")).unwrap().len(), *end);
+    }
+
+    // new changes are appended after the last good record
+    std::fs::write(dir.join("wal"), &wal[..wal.len() - 3]).unwrap();
+    let mut tr: LoggedRTree<2, f64, u64> = LoggedRTree::open(&dir).unwrap();
+    tr.insert(pts[299], 299).unwrap();
+    drop(tr);
+    let tr: LoggedRTree<2, f64, u64> = LoggedRTree::open(&dir).unwrap();
+    let mut state = states[states.len() - 2].1.clone();
+    state.push((pts[299], 299));
+    assert!(items(&tr) == state);
+    drop(tr);
+
+    // a corrupt record ends the replay
+    let mut bad = wal.clone();
+    let start = states[10].0 as usize;
+    bad[start + 12] ^= 0xFF;
+    std::fs::write(dir.join("wal"), &bad).unwrap();
+    let tr: LoggedRTree<2, f64, u64> = LoggedRTree::open(&dir).unwrap();
+    assert!(items(&tr) == states[10].1);
+    drop(tr);
+
+    // a log from before the checkpoint is not replayed over its snapshot
+    std::fs::write(dir.join("wal"), &old_wal).unwrap();
+    let mut tr: LoggedRTree<2, f64, u64> = LoggedRTree::open(&dir).unwrap();
+    assert!(items(&tr) == states[0].1);
+
+    // a checkpoint empties the log, and keeps the tree
+    for i in 250..299 {
+        tr.insert(pts[i], i as u64).unwrap();
+    }
+    let expect = items(&tr);
+    tr.checkpoint().unwrap();
+    assert_eq!(std::fs::metadata(dir.join("wal")).unwrap().len(), 16);
+    drop(tr);
+    let tr: LoggedRTree<2, f64, u64> = LoggedRTree::open(&dir).unwrap();
+    assert!(items(&tr) == expect);
+    drop(tr);
+
+    // a truncated or corrupt snapshot fails to open
+    let snapshot = std::fs::read(dir.join("snapshot")).unwrap();
+    let mut corrupt = snapshot.clone();
+    // the entry count of the root node
+    corrupt[8 + 56 + 32 + 8..][..8].copy_from_slice(&u64::MAX.to_le_bytes());
+    for bad in [&snapshot[..4], &snapshot[..snapshot.len() - 1], &corrupt] {
+        std::fs::write(dir.join("snapshot"), bad).unwrap();
+        let err = LoggedRTree::<2, f64, u64>::open(&dir).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde() {
+    let pts = random_points(5_000);
+    let mut tr = RTree::new();
+    for i in 0..pts.len() {
+        tr.insert(pts[i], i);
+    }
+    for i in 0..pts.len() / 2 {
+        tr.remove(pts[i], &i);
+    }
+    let json = serde_json::to_string(&tr).unwrap();
+    let mut tr2: RTree<2, f64, usize> = serde_json::from_str(&json).unwrap();
+    // the structure is unchanged
+    assert_eq!(format!("{:#?}", tr), format!("{:#?}", tr2));
+    assert_eq!(tr2.len(), tr.len());
+    tr2.validate().unwrap();
+    for i in 0..pts.len() / 2 {
+        tr2.insert(pts[i], i);
+    }
+    for i in 0..pts.len() {
+        assert_eq!(tr2.search(pts[i]).filter(|x| x.data == &i).count(), 1);
+    }
+
+    // empty trees, strategies and options
+    let opts = Options {
+        max_items: 4,
+        min_items: 2,
+    };
+    let tr: RTree<3, i32, String, RStar> = RTree::with_strategy(opts, RStar);
+    let json = serde_json::to_string(&tr).unwrap();
+    assert_eq!(
+        json,
+        r#"{"root":null,"len":0,"height":0,"options":{"max_items":4,"min_items":2}}"#
+    );
+    let tr: RTree<3, i32, String, RStar> = serde_json::from_str(&json).unwrap();
+    assert!(tr.is_empty());
+    assert_eq!(tr.options(), opts);
+
+    let rect = Rect::new([1, 2], [3, 4]);
+    let json = serde_json::to_string(&rect).unwrap();
+    assert_eq!(json, r#"{"min":[1,2],"max":[3,4]}"#);
+    assert_eq!(serde_json::from_str::<Rect<2, i32>>(&json).unwrap(), rect);
+    assert!(serde_json::from_str::<Rect<2, i32>>(r#"{"min":[1],"max":[3,4]}"#).is_err());
+    assert!(serde_json::from_str::<Rect<2, i32>>(r#"{"min":[1,2]}"#).is_err());
+
+    // broken trees are not loaded
+    let load = |json: &str| {
+        serde_json::from_str::<RTree<2, i32, i32>>(json).map_err(|err| err.to_string())
+    };
+    let leaf = |height: usize, len: usize, opts: &str, rect: &str| {
+        load(&format!(
+            r#"{{"root":{{"rect":{{"min":[0,0],"max":[2,2]}},"data":{{"Nodes":[{{"rect":{},"data":{{"Item":7}}}}]}}}},"len":{},"height":{},"options":{}}}"#,
+            rect, len, height, opts,
+        ))
+    };
+    let opts = r#"{"max_items":32,"min_items":6}"#;
+    let exact = r#"{"min":[0,0],"max":[2,2]}"#;
+    let inside = r#"{"min":[1,1],"max":[1,1]}"#;
+    let outside = r#"{"min":[1,1],"max":[3,3]}"#;
+    let tr = leaf(0, 1, opts, exact).unwrap();
+    assert_eq!(tr.search(Rect::new([0, 0], [2, 2])).count(), 1);
+    assert!(leaf(0, 1, opts, inside).unwrap_err().contains("union"));
+    assert!(leaf(0, 1, opts, outside).unwrap_err().contains("union"));
+    assert!(leaf(0, 2, opts, exact).unwrap_err().contains("holds"));
+    assert!(leaf(1, 1, opts, exact).unwrap_err().contains("height"));
+    let bad = r#"{"max_items":32,"min_items":0}"#;
+    assert!(leaf(0, 1, bad, exact).unwrap_err().contains("min_items"));
+    let small = r#"{"max_items":3,"min_items":1}"#;
+    let item = r#"{"rect":{"min":[0,0],"max":[2,2]},"data":{"Item":7}}"#;
+    let full = format!(
+        r#"{{"root":{{"rect":{{"min":[0,0],"max":[2,2]}},"data":{{"Nodes":[{},{},{}]}}}},"len":3,"height":0,"options":{}}}"#,
+        item, item, item, small,
+    );
+    assert!(load(&full).unwrap_err().contains("3 children"));
+    let root_item = format!(
+        r#"{{"root":{},"len":1,"height":0,"options":{}}}"#,
+        item, opts
+    );
+    assert!(load(&root_item).unwrap_err().contains("height"));
+    let no_root = format!(r#"{{"root":null,"len":0,"height":1,"options":{}}}"#, opts);
+    assert!(load(&no_root).unwrap_err().contains("height"));
+}
+
+#[test]
+#[should_panic]
+fn fanout_min_too_large() {
+    RTree::<2, f64, usize>::with_options(Options {
+        max_items: 16,
+        min_items: 9,
+    });
+}
+
+#[test]
+#[should_panic]
+fn fanout_min_zero() {
+    RTree::<2, f64, usize>::with_options(Options {
+        max_items: 16,
+        min_items: 0,
+    });
+}
+
+#[test]
+#[should_panic]
+fn fanout_max_too_small() {
+    RTree::<2, f64, usize>::with_options(Options {
+        max_items: 2,
+        min_items: 1,
+    });
+}
+
+fn test_hilbert_curve<const D: usize>(bits: u32) {
+    // every cell is visited once, and each step moves to a neighbor cell
+    let side = 1u32 << bits;
+    let mut cells = vec![[0u32; D]; side.pow(D as u32) as usize];
+    let mut seen = vec![false; cells.len()];
+    for n in 0..cells.len() {
+        let mut cell = [0u32; D];
+        let mut rem = n as u32;
+        for i in 0..D {
+            cell[i] = rem % side;
+            rem /= side;
+        }
+        let h = hilbert(cell, bits) as usize;
+        assert!(!seen[h]);
+        seen[h] = true;
+        cells[h] = cell;
+    }
+    for h in 1..cells.len() {
+        let steps: u32 = (0..D).map(|i| cells[h][i].abs_diff(cells[h - 1][i])).sum();
+        assert_eq!(steps, 1);
+    }
+}
+
+#[test]
+fn hilbert_curve() {
+    test_hilbert_curve::<2>(1);
+    test_hilbert_curve::<2>(5);
+    test_hilbert_curve::<3>(4);
+    test_hilbert_curve::<4>(2);
 }
 
 #[test]
@@ -156,6 +1891,54 @@ fn svg() {
     to_svg(CITIES_PTS, "cities.svg");
 }
 
+#[cfg(feature = "svg")]
+#[test]
+fn svg_overlays() {
+    // the view box fits the tree and the overlays
+    let pts = to_pts(CITIES_PTS);
+    let tr: RTree<2, f64, usize> = (0..pts.len())
+        .map(|i| (point2(pts[i][0], pts[i][1]), i))
+        .collect();
+    let rect = tr.rect().unwrap();
+    let query = Rect::new([-20.0, -10.0], [20.0, 10.0]);
+    let results: Vec<_> = tr.search(query).map(|item| item.rect).collect();
+    let svg = Svg::new()
+        .colors(&["#111", "#222"])
+        .query(query)
+        .results(results.iter().copied())
+        .render(&tr);
+    let pad = (rect.max[0] - rect.min[0]) / 40.0;
+    assert!(svg.starts_with(&format!(
+        "<svg viewBox=\"{} {} ",
+        rect.min[0] - pad,
+        -rect.max[1] - pad
+    )));
+    assert!(svg.contains("stroke=\"#111\"") && svg.contains("stroke=\"#222\""));
+    assert!(!svg.contains("stroke=\"red\""));
+    assert_eq!(svg.matches("fill=\"orange\"").count(), results.len());
+    assert_eq!(svg.matches("stroke=\"blue\"").count(), 1);
+    assert_eq!(svg.matches("<circle").count(), pts.len() + results.len());
+
+    // the projection of a 3d tree onto the x and z axes
+    let mut tr = RTree::new();
+    tr.insert(Rect::new([0, 5, 10], [1, 6, 12]), 1);
+    tr.insert(Rect::new([3, 0, 20], [3, 0, 20]), 2);
+    let svg = Svg::new().axes(0, 2).render(&tr);
+    assert!(svg.contains("<rect x=\"0\" y=\"-12\" width=\"1\" height=\"2\""));
+    assert!(svg.contains("<circle cx=\"3\" cy=\"-20\""));
+    assert!(RTree::<2, f64, ()>::new().svg().contains("viewBox"));
+
+    // any integer coordinates can be drawn
+    let mut tr = RTree::new();
+    tr.insert(Rect::new([0u64, 0], [4, 2]), ());
+    assert!(tr
+        .svg()
+        .contains("<rect x=\"0\" y=\"-2\" width=\"4\" height=\"2\""));
+    let mut tr = RTree::new();
+    tr.insert(Rect::new_point([-1i64 << 40, 7]), ());
+    assert!(tr.svg().contains("<circle cx=\"-1099511627776\" cy=\"-7\""));
+}
+
 fn to_pts(pts: &str) -> Vec<[f64; 2]> {
     pts.split(";")
         .map(|x| {
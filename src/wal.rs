@@ -0,0 +1,323 @@
+//! A durable tree, which logs every change to a write-ahead log.
+//!
+//! A [`LoggedRTree`] is kept in a directory of two files. The `snapshot` is
+//! the tree as of the last checkpoint, in the flat format of
+//! [`MappedRTree`], and the `wal` holds the changes since then.
+//!
+//! ```text
+//! snapshot    generation: u64, flat tree
+//! wal         magic "RTRW", version: u32, generation: u64, records
+//! record      crc32: u32, size: u32, op: u8, rect, data, new rect (update)
+//! ```
+//!
+//! The log is only replayed over the snapshot of the same generation. The
+//! records are replayed up to the first one that is incomplete or that does
+//! not match its checksum, which is where the log is cut off after a crash.
+
+use super::mapped::{decode_rect, encode_rect, invalid, read_u32, read_u64, rect_size};
+use super::{Codec, EdgeSnap, InsertStrategy, MappedRTree, Options, RTree, Rect};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::ops::{Add, Mul, Sub};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"RTRW";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 16;
+const RECORD_HEADER_SIZE: usize = 8;
+
+const INSERT: u8 = 1;
+const REMOVE: u8 = 2;
+const UPDATE: u8 = 3;
+
+// crc32 -- the IEEE polynomial, reflected, with a table for every byte.
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+fn crc32(buf: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in buf {
+        crc = CRC32_TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+enum Op<const D: usize, C, T>
+where
+    C: PartialOrd + Copy + Default,
+{
+    Insert(Rect<D, C>, T),
+    Remove(Rect<D, C>, T),
+    Update(Rect<D, C>, T, Rect<D, C>),
+}
+
+/// An RTree that logs every change before it is made, so that it can be
+/// recovered after a crash. The items are encoded with their [`Codec`].
+///
+/// The records are written right away, so they survive when the process
+/// crashes. They survive when the system crashes only after a
+/// [`sync`](LoggedRTree::sync).
+pub struct LoggedRTree<const D: usize, C, T, S = EdgeSnap>
+where
+    C: PartialOrd + Copy + Default,
+{
+    tree: RTree<D, C, T, S>,
+    dir: PathBuf,
+    wal: File,
+    generation: u64,
+}
+
+impl<const D: usize, C, T, S> LoggedRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Codec + Default,
+    T: Codec + PartialEq,
+    S: InsertStrategy + Default,
+{
+    /// Opens the tree in the directory, creating it when it does not
+    /// exist, and replays the log over the last snapshot. Fails with
+    /// `InvalidData` when the snapshot is truncated or corrupt.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<LoggedRTree<D, C, T, S>> {
+        LoggedRTree::open_with_options(dir, Options::default())
+    }
+    /// Opens the tree, like [`open`](LoggedRTree::open), with the provided
+    /// node fanout.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options are invalid, see [`RTree::with_options`].
+    pub fn open_with_options<P: AsRef<Path>>(
+        dir: P,
+        opts: Options,
+    ) -> io::Result<LoggedRTree<D, C, T, S>> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut tree = RTree::with_strategy(opts, S::default());
+        let mut generation = 0;
+        match fs::read(dir.join("snapshot")) {
+            Ok(buf) => {
+                if buf.len() < 8 {
+                    return Err(invalid("snapshot too small"));
+                }
+                generation = read_u64(&buf, 0);
+                let mapped = MappedRTree::<D, C>::open(&buf[8..])?;
+                let mut items = Vec::with_capacity(mapped.len());
+                for item in mapped.iter() {
+                    if item.data.len() != T::SIZE {
+                        return Err(invalid("snapshot item size does not match"));
+                    }
+                    items.push((item.rect, T::decode(item.data)));
+                }
+                tree.extend(items);
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        let mut wal = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join("wal"))?;
+        let buf = fs::read(dir.join("wal"))?;
+        let end = if buf.len() >= HEADER_SIZE
+            && &buf[..4] == MAGIC
+            && read_u32(&buf, 4) == VERSION
+            && read_u64(&buf, 8) == generation
+        {
+            replay(&mut tree, &buf)
+        } else {
+            // an empty log, a log that was cut off in its header, or a log
+            // of an older snapshot that was not reset by its checkpoint
+            reset(&mut wal, generation)?;
+            HEADER_SIZE
+        };
+        // whatever follows the last good record is thrown away, so that new
+        // records are not appended after it
+        wal.set_len(end as u64)?;
+        wal.seek(SeekFrom::Start(end as u64))?;
+        Ok(LoggedRTree {
+            tree,
+            dir,
+            wal,
+            generation,
+        })
+    }
+}
+
+impl<const D: usize, C, T, S> LoggedRTree<D, C, T, S>
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Codec + Default,
+    T: Codec + PartialEq,
+    S: InsertStrategy,
+{
+    /// The tree, for searching.
+    pub fn tree(&self) -> &RTree<D, C, T, S> {
+        &self.tree
+    }
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    pub fn insert(&mut self, rect: Rect<D, C>, data: T) -> io::Result<()> {
+        self.log(INSERT, &rect, &data, None)?;
+        self.tree.insert(rect, data);
+        Ok(())
+    }
+    pub fn remove(&mut self, rect: Rect<D, C>, data: &T) -> io::Result<Option<(Rect<D, C>, T)>> {
+        self.log(REMOVE, &rect, data, None)?;
+        Ok(self.tree.remove(rect, data))
+    }
+    /// Moves an item, see [`RTree::update`].
+    pub fn update(
+        &mut self,
+        old_rect: Rect<D, C>,
+        data: &T,
+        new_rect: Rect<D, C>,
+    ) -> io::Result<bool> {
+        self.log(UPDATE, &old_rect, data, Some(&new_rect))?;
+        Ok(self.tree.update(old_rect, data, new_rect))
+    }
+    /// Makes every change durable.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.wal.sync_data()
+    }
+    /// Writes a new snapshot of the tree, and then empties the log.
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        let generation = self.generation + 1;
+        let mut buf = generation.to_le_bytes().to_vec();
+        self.tree.write_mapped(&mut buf, |data, buf| {
+            let start = buf.len();
+            buf.resize(start + T::SIZE, 0);
+            data.encode(&mut buf[start..]);
+        })?;
+        // the new snapshot replaces the old one all at once
+        let tmp = self.dir.join("snapshot.tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+        fs::rename(&tmp, self.dir.join("snapshot"))?;
+        if let Ok(dir) = File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+        // a crash here leaves a log of the old generation, which is ignored
+        reset(&mut self.wal, generation)?;
+        self.generation = generation;
+        Ok(())
+    }
+    // Appends a record for the change, which is made after it was logged.
+    fn log(
+        &mut self,
+        op: u8,
+        rect: &Rect<D, C>,
+        data: &T,
+        new_rect: Option<&Rect<D, C>>,
+    ) -> io::Result<()> {
+        let mut payload = vec![op];
+        encode_rect(rect, &mut payload);
+        let start = payload.len();
+        payload.resize(start + T::SIZE, 0);
+        data.encode(&mut payload[start..]);
+        if let Some(new_rect) = new_rect {
+            encode_rect(new_rect, &mut payload);
+        }
+        let mut record = Vec::with_capacity(RECORD_HEADER_SIZE + payload.len());
+        record.extend_from_slice(&crc32(&payload).to_le_bytes());
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&payload);
+        let end = self.wal.stream_position()?;
+        if let Err(err) = self.wal.write_all(&record) {
+            // cut off the partial record, or the records that follow it
+            // would be lost on replay
+            self.wal.set_len(end)?;
+            self.wal.seek(SeekFrom::Start(end))?;
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+// Empties the log, leaving only the header.
+fn reset(wal: &mut File, generation: u64) -> io::Result<()> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&generation.to_le_bytes());
+    wal.set_len(0)?;
+    wal.seek(SeekFrom::Start(0))?;
+    wal.write_all(&header)?;
+    wal.sync_data()
+}
+
+// Applies the records of the log to the tree, and returns the offset after
+// the last good record.
+fn replay<const D: usize, C, T, S>(tree: &mut RTree<D, C, T, S>, buf: &[u8]) -> usize
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Codec + Default,
+    T: Codec + PartialEq,
+    S: InsertStrategy,
+{
+    let rsize = rect_size::<D, C>();
+    let mut offset = HEADER_SIZE;
+    while buf.len() - offset >= RECORD_HEADER_SIZE {
+        let crc = read_u32(buf, offset);
+        let size = read_u32(buf, offset + 4) as usize;
+        let Some(payload) = buf[offset + RECORD_HEADER_SIZE..].get(..size) else {
+            break;
+        };
+        if crc32(payload) != crc || size < 1 + rsize + T::SIZE {
+            break;
+        }
+        let rect = decode_rect(&payload[1..]);
+        let data = T::decode(&payload[1 + rsize..][..T::SIZE]);
+        let op = match payload[0] {
+            INSERT if size == 1 + rsize + T::SIZE => Op::Insert(rect, data),
+            REMOVE if size == 1 + rsize + T::SIZE => Op::Remove(rect, data),
+            UPDATE if size == 1 + 2 * rsize + T::SIZE => {
+                Op::Update(rect, data, decode_rect(&payload[1 + rsize + T::SIZE..]))
+            }
+            _ => break,
+        };
+        apply(tree, op);
+        offset += RECORD_HEADER_SIZE + size;
+    }
+    offset
+}
+
+fn apply<const D: usize, C, T, S>(tree: &mut RTree<D, C, T, S>, op: Op<D, C, T>)
+where
+    C: PartialOrd + Copy + Sub<Output = C> + Add<Output = C> + Mul<Output = C> + Default,
+    T: PartialEq,
+    S: InsertStrategy,
+{
+    match op {
+        Op::Insert(rect, data) => tree.insert(rect, data),
+        Op::Remove(rect, data) => {
+            tree.remove(rect, &data);
+        }
+        Op::Update(old_rect, data, new_rect) => {
+            tree.update(old_rect, &data, new_rect);
+        }
+    }
+}